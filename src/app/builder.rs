@@ -1,10 +1,13 @@
 use super::App;
+use crate::spotify::{Spotify, profile::Profile};
 
 use std::sync::Arc;
 
 #[derive(Default)]
 pub struct AppBuilder {
-    cli: bool
+    cli: bool,
+    args: Vec<String>,
+    profile: Profile
 }
 
 impl AppBuilder {
@@ -14,14 +17,26 @@ impl AppBuilder {
             .build()
             .unwrap();
 
+        let spotify = Spotify::default();
+        spotify.set_profile(self.profile);
+
         App {
             rt: Arc::new(runtime),
             cli: self.cli,
-            spotify: Default::default()
+            args: self.args,
+            spotify
         }
     }
 
     pub fn cli(mut self) -> Self {
         self.cli = true; self
     }
+
+    pub fn args(mut self, args: impl IntoIterator<Item = String>) -> Self {
+        self.args = args.into_iter().collect(); self
+    }
+
+    pub fn profile(mut self, profile: Profile) -> Self {
+        self.profile = profile; self
+    }
 }
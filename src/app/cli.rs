@@ -0,0 +1,505 @@
+use super::{
+    App,
+    AppResult,
+    command::{Command, Adjust, Choice, PlayTarget, ExitStatus, USAGE},
+    status::{PlaybackStatus, StatusFormat}
+};
+use crate::spotify::{
+    item_title,
+    auth::{self, authenticate, build_client, oauth_client, refresh_if_expired},
+    profile::Profile,
+    scopes::{self, MissingScopes},
+    vault::Vault,
+    io::{self, Io, IoEvent},
+    search::{search, SearchMatch},
+    uri::SpotifyUri
+};
+
+use std::{
+    collections::HashSet,
+    io::Write,
+    time::Duration
+};
+use anyhow::{Context, bail, anyhow};
+use rspotify::{
+    prelude::OAuthClient,
+    model::{AdditionalType, CurrentPlaybackContext, Device}
+};
+
+impl App {
+    pub(super) fn run_cli(&mut self) -> ExitStatus {
+        let cmd = match Command::parse(&self.args) {
+            Ok(cmd) => cmd,
+            Err(e) => {
+                eprintln!("Error: {}\n\n{}", e, USAGE);
+                return e.exit_status();
+            }
+        };
+
+        match cmd {
+            Command::Help => {
+                print!("{}", USAGE);
+                return ExitStatus::Success;
+            },
+            Command::Profiles | Command::Logout => {
+                let result = match cmd {
+                    Command::Profiles => self.list_profiles(),
+                    _ => self.logout()
+                };
+
+                return match result {
+                    Ok(_) => ExitStatus::Success,
+                    Err(e) => {
+                        eprintln!("Error: {:#}", e);
+                        ExitStatus::Failure
+                    }
+                };
+            },
+            Command::Login { browser, qr } => {
+                return match self.rt.block_on(self.login(browser, qr)) {
+                    Ok(_) => ExitStatus::Success,
+                    Err(e) => {
+                        eprintln!("Error: {:#}", e);
+                        ExitStatus::AuthFailed
+                    }
+                };
+            },
+            _ => ()
+        }
+
+        // Authenticate Spotify client
+        let profile = self.spotify.profile();
+        let client = Vault::for_profile(&profile).and_then(|vault| {
+            self.spotify.set_vault(vault.clone());
+            self.rt.block_on(oauth_client(&profile, &vault))
+        });
+
+        match client {
+            Ok(client) => self.spotify.set_client(client),
+            Err(e) if auth::is_revoked(&e) => {
+                eprintln!("Error: {:#}\nRun 'imspotify login' to log in again", e);
+                return ExitStatus::AuthFailed;
+            },
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                return ExitStatus::AuthFailed;
+            }
+        }
+
+        let result = self.rt.block_on(async {
+            let user = self.spotify.client().me().await?;
+            eprintln!("Logged-in as: {}", user.display_name.unwrap_or(String::new()));
+
+            AppResult::Ok(())
+        });
+
+        // Parse and handle commands
+        let result = result.and_then(|_| match cmd {
+            Command::Shell => self.run_shell(),
+            Command::Run(path) => self.rt.block_on(self.run_script(path.as_deref())),
+            cmd => self.rt.block_on(self.handle_command(cmd))
+        });
+
+        match result {
+            Ok(_) => ExitStatus::Success,
+            Err(e) if auth::is_revoked(&e) => {
+                eprintln!("Error: {:#}\nRun 'imspotify login' to log in again", e);
+                ExitStatus::AuthFailed
+            },
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                ExitStatus::Failure
+            }
+        }
+    }
+
+    pub async fn handle_command(&self, cmd: Command) -> AppResult<()> {
+        let client = &self.spotify.client();
+        let vault = self.spotify.vault();
+
+        // The encrypted token cache may have locked again in a long session
+        if vault.needs_unlock() {
+            authenticate(client, &vault).await?;
+        }
+
+        refresh_if_expired(client, &vault).await?;
+
+        let granted = auth::granted_scopes(client).await;
+        let missing = scopes::missing(&granted, cmd.required_scopes());
+
+        if !missing.is_empty() {
+            self.grant_scopes(granted, missing).await?;
+        }
+
+        match cmd {
+            Command::Help => {
+                print!("{}", USAGE);
+            },
+            Command::Shell => {
+                bail!("The shell can only be started from the command line");
+            },
+            Command::Run(_) => {
+                bail!("Scripts can't be run from within a script");
+            },
+            Command::Profiles => {
+                self.list_profiles()?;
+            },
+            Command::Login { browser, qr } => {
+                self.login(browser, qr).await?;
+            },
+            Command::Logout => {
+                self.logout()?;
+                vault.lock();
+                *client.token.lock().await.unwrap() = None;
+            },
+            Command::Watch { interval } => {
+                self.watch(interval.map(Duration::from_secs)).await?;
+            },
+            Command::Playlists { json } => {
+                self.list_playlists(json).await?;
+            },
+            Command::Playlist(cmd) => {
+                self.handle_playlist_command(cmd).await?;
+            },
+            Command::Like(uri) => {
+                self.set_saved(uri, true).await?;
+            },
+            Command::Unlike(uri) => {
+                self.set_saved(uri, false).await?;
+            },
+            Command::Liked { limit, json } => {
+                self.list_liked(limit, json).await?;
+            },
+            Command::Resume => {
+                client.resume_playback(None, None).await
+                    .context("Unable to resume playback")?;
+            },
+            Command::Pause => {
+                client.pause_playback(None).await
+                    .context("Unable to pause playback")?;
+            },
+            Command::Toggle => {
+                let playback = self.current_playback().await?;
+
+                if playback.is_playing {
+                    client.pause_playback(None).await
+                        .context("Unable to pause playback")?;
+                } else {
+                    client.resume_playback(None, None).await
+                        .context("Unable to resume playback")?;
+                }
+            },
+            Command::Next => {
+                client.next_track(None).await
+                    .context("Unable to skip to the next track")?;
+            },
+            Command::Previous => {
+                client.previous_track(None).await
+                    .context("Unable to skip to the previous track")?;
+            },
+            Command::Seek(position) => {
+                let position = match position {
+                    Adjust::To(millis) => millis,
+                    Adjust::By(delta) => {
+                        let playback = self.current_playback().await?;
+                        let progress = playback.progress
+                            .unwrap_or_default()
+                            .as_millis() as i64;
+
+                        u32::try_from(progress.saturating_add(delta).max(0))
+                            .context("The seek position is out of range")?
+                    }
+                };
+
+                client.seek_track(position, None).await
+                    .context("Unable to seek")?;
+            },
+            Command::Volume(volume) => {
+                let volume = match volume {
+                    Adjust::To(percent) => percent,
+                    Adjust::By(delta) => {
+                        let playback = self.current_playback().await?;
+                        let current = playback.device.volume_percent
+                            .context("The active device doesn't support volume control")?;
+
+                        (current as i64 + delta).clamp(0, 100) as u32
+                    }
+                };
+
+                client.volume(volume as u8, None).await
+                    .context("Unable to set volume")?;
+            },
+            Command::Shuffle(state) => {
+                client.shuffle(state, None).await
+                    .context("Unable to set shuffle state")?;
+            },
+            Command::Repeat(state) => {
+                client.repeat(&state, None).await
+                    .context("Unable to set repeat mode")?;
+            },
+            Command::Status(format) => {
+                let playback = client.current_playback(
+                    None,
+                    Some(vec![&AdditionalType::Episode, &AdditionalType::Track])
+                ).await.context("Unable to fetch current playback")?;
+
+                match playback.as_ref().and_then(PlaybackStatus::new) {
+                    Some(status) => println!("{}", status.render(&format)),
+                    None => match format {
+                        StatusFormat::Json => println!("null"),
+                        StatusFormat::Template(_) => println!(),
+                        StatusFormat::Text => println!("Nothing is playing right now")
+                    }
+                }
+            },
+            Command::Play(target) => {
+                let uri = self.resolve_target(target).await?;
+                self.play_uri(&uri).await?;
+            },
+            Command::Devices { json } => {
+                io::handle_event(IoEvent::FetchDevices, &Io::default(), self).await
+                    .context("Unable to fetch devices")?;
+
+                let app_state = self.spotify.state.lock().await;
+                let devices = app_state.devices.as_deref().unwrap_or_default();
+
+                if json {
+                    println!("{}", serde_json::to_string(devices)?);
+                } else if devices.is_empty() {
+                    println!("No devices available");
+                } else {
+                    for device in devices {
+                        println!(
+                            "{} {:<32} {:<12} {:>4}  {}",
+                            if device.is_active { "*" } else { " " },
+                            device.name,
+                            format!("{:?}", device._type),
+                            device.volume_percent
+                                .map(|v| format!("{}%", v))
+                                .unwrap_or(String::from("-")),
+                            device.id.as_deref().unwrap_or_default()
+                        );
+                    }
+                }
+            },
+            Command::Transfer(target) => {
+                io::handle_event(IoEvent::FetchDevices, &Io::default(), self).await
+                    .context("Unable to fetch devices")?;
+
+                let device_id = {
+                    let app_state = self.spotify.state.lock().await;
+                    let devices = app_state.devices.as_deref().unwrap_or_default();
+
+                    find_device(devices, &target)?
+                };
+
+                io::handle_event(IoEvent::TransferPlayback(device_id), &Io::default(), self).await
+                    .context("Unable to transfer playback")?;
+            },
+            Command::Queue { json } => {
+                io::handle_event(IoEvent::FetchQueue, &Io::default(), self).await
+                    .context("Unable to fetch the queue")?;
+
+                let app_state = self.spotify.state.lock().await;
+
+                if let Some(queue) = &app_state.queue {
+                    if json {
+                        println!("{}", serde_json::to_string(queue)?);
+                    } else {
+                        if let Some(item) = &queue.currently_playing {
+                            println!("Now playing: {}", item_title(item));
+                        }
+
+                        for (i, item) in queue.queue.iter().enumerate() {
+                            println!("{:>3}. {}", i + 1, item_title(item));
+                        }
+                    }
+                }
+            },
+            Command::QueueAdd(target) => {
+                let uri = self.resolve_target(target).await?;
+
+                io::handle_event(IoEvent::AddToQueue(uri), &Io::default(), self).await
+                    .context("Unable to add to the queue")?;
+            }
+        };
+
+        Ok(())
+    }
+
+    fn list_profiles(&self) -> AppResult<()> {
+        let active = self.spotify.profile();
+
+        for profile in Profile::list()? {
+            let marker = if profile == active { "*" } else { " " };
+            println!("{} {}", marker, profile.name);
+        }
+
+        Ok(())
+    }
+
+    // Always logs in from scratch, replacing any cached token
+    async fn login(&self, browser: bool, qr: bool) -> AppResult<()> {
+        let profile = self.spotify.profile();
+        let vault = Vault::for_profile(&profile)?;
+        let client = build_client(&profile, &vault)?;
+
+        if vault.is_enabled() {
+            auth::unlock_from_terminal(&client, &vault).await?;
+        }
+
+        let client = auth::with_granted_scopes(&client).await;
+
+        match browser {
+            true => auth::login(&client, &vault).await?,
+            false => auth::login_headless(&client, &vault, qr).await?
+        }
+
+        self.spotify.set_client(client);
+        self.spotify.set_vault(vault);
+
+        eprintln!("Logged in, the token of profile '{}' has been cached", profile.name);
+
+        Ok(())
+    }
+
+    // Explains which scopes are missing and offers to log in again asking for them
+    async fn grant_scopes(&self, granted: HashSet<String>, missing: Vec<&'static str>) -> AppResult<()> {
+        let error = MissingScopes(missing);
+        eprintln!("{}", error);
+        // Pasting the redirect URL works on remote machines without a browser
+        eprint!("Authorize it now in the browser, or by pasting the redirect URL? [b/p/N] ");
+        std::io::stderr().flush()?;
+
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+
+        let headless = match answer.trim().to_ascii_lowercase().as_str() {
+            "b" => false,
+            "p" => true,
+            _ => return Err(anyhow!(error))
+        };
+
+        let requested = granted
+            .into_iter()
+            .chain(error.0.iter().map(|s| s.to_string()));
+
+        let client = auth::with_scopes(&self.spotify.client(), requested);
+        let vault = self.spotify.vault();
+
+        match headless {
+            true => auth::login_headless(&client, &vault, false).await?,
+            false => auth::login(&client, &vault).await?
+        }
+
+        self.spotify.set_client(client);
+
+        Ok(())
+    }
+
+    fn logout(&self) -> AppResult<()> {
+        let profile = self.spotify.profile();
+
+        if auth::logout(&profile)? {
+            println!("Logged out of profile '{}'", profile.name);
+        } else {
+            println!("Profile '{}' has no cached token", profile.name);
+        }
+
+        Ok(())
+    }
+
+    pub(super) async fn resolve_target(&self, target: PlayTarget) -> AppResult<SpotifyUri> {
+        match target {
+            PlayTarget::Uri(uri) => Ok(uri),
+            PlayTarget::Search { kind, query, choice } => {
+                let matches = search(&self.spotify.client(), kind, &query, 10).await?;
+                let selected = choose_match(matches, choice)?;
+
+                println!("Selected: {}", format_match(&selected));
+                Ok(selected.uri)
+            }
+        }
+    }
+
+    async fn play_uri(&self, uri: &SpotifyUri) -> AppResult<()> {
+        let client = &self.spotify.client();
+
+        if let Some(id) = uri.as_playable() {
+            client.start_uris_playback([id], None, None, None).await
+                .context("Unable to start playback")?;
+        } else if let Some(id) = uri.as_context() {
+            client.start_context_playback(id, None, None, None).await
+                .context("Unable to start playback")?;
+        }
+
+        Ok(())
+    }
+
+    pub(super) async fn current_playback(&self) -> AppResult<CurrentPlaybackContext> {
+        let playback = self.spotify.client().current_playback(
+            None,
+            Some(vec![&AdditionalType::Episode, &AdditionalType::Track])
+        ).await.context("Unable to fetch current playback")?;
+
+        match playback {
+            Some(playback) => Ok(playback),
+            None => bail!("Nothing is playing right now")
+        }
+    }
+}
+
+fn choose_match(matches: Vec<SearchMatch>, choice: Choice) -> AppResult<SearchMatch> {
+    if matches.is_empty() {
+        bail!("No matches found");
+    }
+
+    for (i, m) in matches.iter().enumerate() {
+        println!("{:>3}. {}", i + 1, format_match(m));
+    }
+
+    let index = match choice {
+        Choice::Best => 1,
+        Choice::Select(n) => n,
+        Choice::Pick => {
+            eprint!("Select a match [1-{}]: ", matches.len());
+
+            let mut input = String::new();
+            std::io::stdin().read_line(&mut input)?;
+
+            input.trim().parse::<usize>()
+                .context("Invalid selection")?
+        }
+    };
+
+    matches
+        .into_iter()
+        .nth(index.wrapping_sub(1))
+        .with_context(|| format!("There is no match number {}", index))
+}
+
+fn find_device(devices: &[Device], target: &str) -> AppResult<String> {
+    if let Some(id) = devices.iter().find_map(|d| d.id.as_deref().filter(|id| *id == target)) {
+        return Ok(id.to_owned());
+    }
+
+    let matches = devices
+        .iter()
+        .filter(|d| d.name.eq_ignore_ascii_case(target))
+        .filter_map(|d| d.id.clone())
+        .collect::<Vec<String>>();
+
+    match &matches[..] {
+        [id] => Ok(id.clone()),
+        [] => bail!("No device named '{}'", target),
+        _ => bail!("Multiple devices are named '{}', please use the device id", target)
+    }
+}
+
+fn format_match(m: &SearchMatch) -> String {
+    if m.detail.is_empty() {
+        m.name.clone()
+    } else {
+        format!("{} - {}", m.name, m.detail)
+    }
+}
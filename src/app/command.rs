@@ -0,0 +1,713 @@
+use super::status::StatusFormat;
+use crate::spotify::{
+    profile::Profile,
+    scopes,
+    search::SearchKind,
+    uri::SpotifyUri
+};
+
+use std::{fmt, path::PathBuf};
+use rspotify::model::RepeatState;
+
+pub const USAGE: &str = "\
+Usage: imspotify [--profile <NAME>] [COMMAND] [ARGS...]
+
+Runs the graphical interface when no command is given.
+
+Options:
+    --profile <NAME>            Use a named account profile with its own
+                                token cache and settings
+
+Commands:
+    resume                      Resume playback
+    pause                       Pause playback
+    toggle                      Toggle between playing and paused
+    next                        Skip to the next track
+    previous                    Skip to the previous track
+    seek <POSITION>             Seek to a position (90, 1:30, +10, -10)
+    volume <PERCENT>            Set the volume (0-100, +N, -N)
+    shuffle <on|off>            Set the shuffle state
+    repeat <off|track|context>  Set the repeat mode
+    status [--json | --format <TEMPLATE>]
+                                Print the current playback, e.g.
+                                --format \"{artist} - {title} [{progress}/{duration}]\"
+                                Fields: title, artist, album, progress, duration,
+                                device, volume, shuffle, repeat, state, is_playing, uri
+    play <URI | URL | QUERY>    Play a Spotify URI, open.spotify.com link or track
+    play --track|--album|--artist|--playlist <QUERY> [--pick | --select <N>]
+                                Search and play the best match, or choose one
+                                of the listed matches interactively or by number
+    devices [--json]            List the available Spotify Connect devices
+    transfer <NAME | ID>        Transfer playback to another device
+    queue [--json]              Print the playback queue
+    queue add <URI | URL | QUERY>
+                                Add a track or episode to the queue
+    watch [--interval <SECS>]   Print playback changes as JSON lines
+    playlists [--json]          List your playlists
+    playlist show <PLAYLIST> [--limit <N>] [--offset <N>] [--json]
+                                List the items of a playlist
+    playlist create <NAME> [--public] [--description <TEXT>]
+                                Create a new playlist
+    playlist rename <PLAYLIST> <NAME>
+                                Rename a playlist
+    playlist delete <PLAYLIST>  Delete (unfollow) a playlist
+    playlist add <PLAYLIST> <URI | URL | QUERY>
+                                Add a track or episode to a playlist
+    playlist remove <PLAYLIST> <URI | URL | QUERY>
+                                Remove a track or episode from a playlist
+                                PLAYLIST is a name, an id, a URI or a link
+    like [URI | URL]            Save the playing or given track to Liked Songs
+    unlike [URI | URL]          Remove the playing or given track from Liked Songs
+    liked [--limit <N>] [--json]
+                                List your Liked Songs
+    --shell                     Start an interactive shell
+    run [SCRIPT]                Run commands from a file, or from stdin
+                                Scripts may also use the directives
+                                'sleep <SECS>', 'wait-until-track-end' and
+                                'on-error <continue|abort>'
+    profiles                    List the account profiles
+    login [--no-browser] [--qr]
+                                Log in and cache the token, then exit
+                                --no-browser prints the URL and reads the
+                                redirect URL or code back, --qr also prints
+                                the URL as a QR code and implies it
+    logout                      Delete the cached token of the active profile
+    help                        Print this message
+";
+
+pub const COMMANDS: &[&str] = &[
+    "help",
+    "resume",
+    "pause",
+    "toggle",
+    "next",
+    "previous",
+    "seek",
+    "volume",
+    "shuffle",
+    "repeat",
+    "status",
+    "play",
+    "devices",
+    "transfer",
+    "queue",
+    "shell",
+    "watch",
+    "playlists",
+    "playlist",
+    "like",
+    "unlike",
+    "liked",
+    "run",
+    "profiles",
+    "login",
+    "logout"
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    Success = 0,
+    Failure = 1,
+    Usage = 2,
+    UnknownCommand = 3,
+    AuthFailed = 4
+}
+
+impl ExitStatus {
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Adjust {
+    To(u32),
+    By(i64)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Choice {
+    Best,
+    Pick,
+    Select(usize)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlayTarget {
+    Uri(SpotifyUri),
+    Search {
+        kind: SearchKind,
+        query: String,
+        choice: Choice
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlaylistCommand {
+    Show {
+        playlist: String,
+        limit: u32,
+        offset: u32,
+        json: bool
+    },
+    Create {
+        name: String,
+        public: bool,
+        description: Option<String>
+    },
+    Rename {
+        playlist: String,
+        name: String
+    },
+    Delete {
+        playlist: String
+    },
+    Add {
+        playlist: String,
+        target: PlayTarget
+    },
+    Remove {
+        playlist: String,
+        target: PlayTarget
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Help,
+    Resume,
+    Pause,
+    Toggle,
+    Next,
+    Previous,
+    Seek(Adjust),
+    Volume(Adjust),
+    Shuffle(bool),
+    Repeat(RepeatState),
+    Status(StatusFormat),
+    Play(PlayTarget),
+    Devices { json: bool },
+    Transfer(String),
+    Queue { json: bool },
+    QueueAdd(PlayTarget),
+    Shell,
+    Watch { interval: Option<u64> },
+    Playlists { json: bool },
+    Playlist(PlaylistCommand),
+    Like(Option<SpotifyUri>),
+    Unlike(Option<SpotifyUri>),
+    Liked { limit: u32, json: bool },
+    Run(Option<PathBuf>),
+    Profiles,
+    Login { browser: bool, qr: bool },
+    Logout
+}
+
+#[derive(Debug)]
+pub enum CommandError {
+    Unknown(String),
+    Usage(String)
+}
+
+impl CommandError {
+    pub fn exit_status(&self) -> ExitStatus {
+        match self {
+            CommandError::Unknown(_) => ExitStatus::UnknownCommand,
+            CommandError::Usage(_) => ExitStatus::Usage
+        }
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::Unknown(cmd) => write!(f, "Unknown command '{}'", cmd),
+            CommandError::Usage(msg) => write!(f, "{}", msg)
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+impl Command {
+    pub fn required_scopes(&self) -> &'static [&'static str] {
+        match self {
+            Command::Help
+            | Command::Shell
+            | Command::Run(_)
+            | Command::Profiles
+            | Command::Login { .. }
+            | Command::Logout => &[],
+            Command::Resume
+            | Command::Pause
+            | Command::Next
+            | Command::Previous
+            | Command::Shuffle(_)
+            | Command::Repeat(_)
+            | Command::Play(_)
+            | Command::Transfer(_)
+            | Command::QueueAdd(_)
+            | Command::Seek(Adjust::To(_))
+            | Command::Volume(Adjust::To(_)) => &[scopes::MODIFY_PLAYBACK],
+            Command::Toggle
+            | Command::Seek(Adjust::By(_))
+            | Command::Volume(Adjust::By(_)) => &[scopes::READ_PLAYBACK, scopes::MODIFY_PLAYBACK],
+            Command::Status(_)
+            | Command::Devices { .. }
+            | Command::Watch { .. } => &[scopes::READ_PLAYBACK],
+            Command::Queue { .. } => &[scopes::READ_PLAYBACK, scopes::READ_CURRENTLY_PLAYING],
+            Command::Playlists { .. }
+            | Command::Playlist(PlaylistCommand::Show { .. }) => &[scopes::READ_PLAYLISTS],
+            Command::Playlist(_) => &[
+                scopes::READ_PLAYLISTS,
+                scopes::MODIFY_PRIVATE_PLAYLISTS,
+                scopes::MODIFY_PUBLIC_PLAYLISTS
+            ],
+            Command::Like(None) | Command::Unlike(None) => &[scopes::READ_PLAYBACK, scopes::MODIFY_LIBRARY],
+            Command::Like(_) | Command::Unlike(_) => &[scopes::MODIFY_LIBRARY],
+            Command::Liked { .. } => &[scopes::READ_LIBRARY]
+        }
+    }
+
+    pub fn parse<S: AsRef<str>>(args: &[S]) -> Result<Command, CommandError> {
+        let args = args
+            .iter()
+            .map(|a| a.as_ref())
+            .collect::<Vec<&str>>();
+
+        let (name, rest) = match args.split_first() {
+            Some((name, rest)) => (*name, rest),
+            None => return Ok(Command::Help)
+        };
+
+        let name = match name {
+            "--help" | "-h" => "help",
+            "--shell" => "shell",
+            "--resume" => "resume",
+            "--pause" => "pause",
+            "prev" => "previous",
+            name => name
+        };
+
+        let cmd = match (name, rest) {
+            ("help", []) => Command::Help,
+            ("shell", []) => Command::Shell,
+            ("watch", []) => Command::Watch { interval: None },
+            ("watch", ["--interval", secs]) => match secs.parse::<u64>() {
+                Ok(secs) if secs > 0 => Command::Watch { interval: Some(secs) },
+                _ => return Err(CommandError::Usage(format!("Invalid interval '{}'", secs)))
+            },
+            ("watch", _) => {
+                return Err(CommandError::Usage(String::from("Expected 'watch [--interval <SECS>]'")))
+            },
+            ("playlists", []) => Command::Playlists { json: false },
+            ("playlists", ["--json"]) => Command::Playlists { json: true },
+            ("playlist", _) => Command::Playlist(parse_playlist_command(rest)?),
+            ("run", []) => Command::Run(None),
+            ("run", ["-"]) => Command::Run(None),
+            ("run", [path]) => Command::Run(Some(PathBuf::from(path))),
+            ("run", _) => return Err(CommandError::Usage(String::from("Expected 'run [SCRIPT]'"))),
+            ("profiles", []) => Command::Profiles,
+            ("login", _) => {
+                let mut args = rest.to_vec();
+                let no_browser = take_flag(&mut args, "--no-browser");
+                let qr = take_flag(&mut args, "--qr");
+
+                if !args.is_empty() {
+                    return Err(CommandError::Usage(String::from("Expected 'login [--no-browser] [--qr]'")));
+                }
+
+                Command::Login { browser: !no_browser && !qr, qr }
+            },
+            ("logout", []) => Command::Logout,
+            ("like", _) => Command::Like(parse_track_uri(rest)?),
+            ("unlike", _) => Command::Unlike(parse_track_uri(rest)?),
+            ("liked", _) => {
+                let mut args = rest.to_vec();
+                let json = take_flag(&mut args, "--json");
+                let limit = parse_number("--limit", take_option(&mut args, "--limit")?, 20)?;
+
+                if !args.is_empty() {
+                    return Err(CommandError::Usage(String::from("Expected 'liked [--limit <N>] [--json]'")));
+                }
+
+                Command::Liked { limit, json }
+            },
+            ("resume", []) => Command::Resume,
+            ("pause", []) => Command::Pause,
+            ("toggle", []) => Command::Toggle,
+            ("next", []) => Command::Next,
+            ("previous", []) => Command::Previous,
+            ("seek", _) => Command::Seek(parse_position(single_arg(name, rest)?)?),
+            ("volume", _) => Command::Volume(parse_volume(single_arg(name, rest)?)?),
+            ("shuffle", _) => Command::Shuffle(parse_switch(single_arg(name, rest)?)?),
+            ("repeat", _) => Command::Repeat(parse_repeat(single_arg(name, rest)?)?),
+            ("status", _) => Command::Status(parse_status_format(rest)?),
+            ("play", _) => Command::Play(parse_play_target(rest)?),
+            ("devices", []) => Command::Devices { json: false },
+            ("devices", ["--json"]) => Command::Devices { json: true },
+            ("transfer", []) => {
+                return Err(CommandError::Usage(String::from("'transfer' requires a device name or id")))
+            },
+            ("transfer", _) => Command::Transfer(rest.join(" ")),
+            ("queue", []) => Command::Queue { json: false },
+            ("queue", ["--json"]) => Command::Queue { json: true },
+            ("queue", ["add", query @ ..]) => Command::QueueAdd(parse_play_target(query)?),
+            ("queue", _) => {
+                return Err(CommandError::Usage(String::from("Expected 'queue [--json]' or 'queue add <URI | QUERY>'")))
+            },
+            (name, _) if COMMANDS.contains(&name) => {
+                return Err(CommandError::Usage(format!("'{}' takes no arguments", name)))
+            },
+            (name, _) => return Err(CommandError::Unknown(name.to_owned()))
+        };
+
+        Ok(cmd)
+    }
+}
+
+// Removes the global '--profile <NAME>' option from the arguments
+pub fn take_profile(args: &mut Vec<String>) -> Result<Profile, CommandError> {
+    let index = match args.iter().position(|a| a == "--profile") {
+        Some(index) => index,
+        None => return Ok(Profile::default())
+    };
+
+    if index + 1 >= args.len() {
+        return Err(CommandError::Usage(String::from("'--profile' requires a name")));
+    }
+
+    let name = args.remove(index + 1);
+    args.remove(index);
+
+    Profile::new(&name).map_err(|e| CommandError::Usage(e.to_string()))
+}
+
+// Splits a line into arguments, keeping quoted parts together
+pub fn split_args(line: &str) -> Result<Vec<String>, CommandError> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut quote = None;
+    let mut in_arg = false;
+
+    for c in line.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => current.push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                in_arg = true;
+            },
+            (None, c) if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            },
+            (None, c) => {
+                current.push(c);
+                in_arg = true;
+            }
+        }
+    }
+
+    if quote.is_some() {
+        return Err(CommandError::Usage(String::from("Unterminated quote")));
+    }
+
+    if in_arg {
+        args.push(current);
+    }
+
+    Ok(args)
+}
+
+fn single_arg<'a>(name: &str, rest: &[&'a str]) -> Result<&'a str, CommandError> {
+    match rest {
+        [arg] => Ok(arg),
+        [] => Err(CommandError::Usage(format!("'{}' requires an argument", name))),
+        _ => Err(CommandError::Usage(format!("'{}' takes a single argument", name)))
+    }
+}
+
+fn parse_position(arg: &str) -> Result<Adjust, CommandError> {
+    let invalid = || CommandError::Usage(format!("Invalid position '{}'", arg));
+
+    // Milliseconds, out of range positions are invalid rather than wrapping
+    let parse_millis = |s: &str| -> Result<u32, CommandError> {
+        let secs = match s.split_once(':') {
+            Some((m, s)) => {
+                let m = m.parse::<u32>().map_err(|_| invalid())?;
+                let s = s.parse::<u32>().map_err(|_| invalid())?;
+                if s >= 60 { return Err(invalid()) }
+                m.checked_mul(60).and_then(|m| m.checked_add(s))
+            },
+            None => Some(s.parse::<u32>().map_err(|_| invalid())?)
+        };
+
+        secs.and_then(|secs| secs.checked_mul(1000)).ok_or_else(invalid)
+    };
+
+    if let Some(s) = arg.strip_prefix('+') {
+        Ok(Adjust::By(parse_millis(s)? as i64))
+    } else if let Some(s) = arg.strip_prefix('-') {
+        Ok(Adjust::By(-(parse_millis(s)? as i64)))
+    } else {
+        Ok(Adjust::To(parse_millis(arg)?))
+    }
+}
+
+fn parse_volume(arg: &str) -> Result<Adjust, CommandError> {
+    let invalid = || CommandError::Usage(format!("Invalid volume '{}'", arg));
+
+    if let Some(v) = arg.strip_prefix('+') {
+        Ok(Adjust::By(v.parse::<u8>().map_err(|_| invalid())? as i64))
+    } else if let Some(v) = arg.strip_prefix('-') {
+        Ok(Adjust::By(-(v.parse::<u8>().map_err(|_| invalid())? as i64)))
+    } else {
+        match arg.parse::<u32>() {
+            Ok(v) if v <= 100 => Ok(Adjust::To(v)),
+            _ => Err(invalid())
+        }
+    }
+}
+
+fn parse_switch(arg: &str) -> Result<bool, CommandError> {
+    match arg {
+        "on" | "true" => Ok(true),
+        "off" | "false" => Ok(false),
+        _ => Err(CommandError::Usage(format!("Expected 'on' or 'off', got '{}'", arg)))
+    }
+}
+
+fn parse_repeat(arg: &str) -> Result<RepeatState, CommandError> {
+    match arg {
+        "off" => Ok(RepeatState::Off),
+        "track" => Ok(RepeatState::Track),
+        "context" | "all" => Ok(RepeatState::Context),
+        _ => Err(CommandError::Usage(format!("Expected 'off', 'track' or 'context', got '{}'", arg)))
+    }
+}
+
+// Removes a boolean flag from the arguments, returning whether it was present
+fn take_flag(args: &mut Vec<&str>, flag: &str) -> bool {
+    let len = args.len();
+    args.retain(|a| *a != flag);
+    args.len() != len
+}
+
+// Removes an option and its value from the arguments
+fn take_option<'a>(args: &mut Vec<&'a str>, option: &str) -> Result<Option<&'a str>, CommandError> {
+    let index = match args.iter().position(|a| *a == option) {
+        Some(index) => index,
+        None => return Ok(None)
+    };
+
+    if index + 1 >= args.len() {
+        return Err(CommandError::Usage(format!("'{}' requires a value", option)));
+    }
+
+    let value = args.remove(index + 1);
+    args.remove(index);
+
+    Ok(Some(value))
+}
+
+fn parse_number(option: &str, value: Option<&str>, default: u32) -> Result<u32, CommandError> {
+    match value {
+        Some(value) => value.parse::<u32>()
+            .map_err(|_| CommandError::Usage(format!("Invalid value '{}' for '{}'", value, option))),
+        None => Ok(default)
+    }
+}
+
+fn parse_playlist_command(rest: &[&str]) -> Result<PlaylistCommand, CommandError> {
+    let usage = || CommandError::Usage(String::from(
+        "Expected 'playlist <show | create | rename | delete | add | remove> ...'"
+    ));
+
+    let (action, rest) = rest.split_first().ok_or_else(usage)?;
+    let mut args = rest.to_vec();
+
+    let cmd = match *action {
+        "show" => {
+            let json = take_flag(&mut args, "--json");
+            let limit = parse_number("--limit", take_option(&mut args, "--limit")?, 50)?;
+            let offset = parse_number("--offset", take_option(&mut args, "--offset")?, 0)?;
+
+            if !(1..=100).contains(&limit) {
+                return Err(CommandError::Usage(String::from("'--limit' must be between 1 and 100")));
+            }
+
+            match args[..] {
+                [playlist] => PlaylistCommand::Show { playlist: playlist.to_owned(), limit, offset, json },
+                _ => return Err(CommandError::Usage(String::from("'playlist show' requires a single playlist")))
+            }
+        },
+        "create" => {
+            let public = take_flag(&mut args, "--public");
+            let description = take_option(&mut args, "--description")?.map(String::from);
+
+            if args.is_empty() {
+                return Err(CommandError::Usage(String::from("'playlist create' requires a name")));
+            }
+
+            PlaylistCommand::Create { name: args.join(" "), public, description }
+        },
+        "rename" => match args[..] {
+            [playlist, name] => PlaylistCommand::Rename {
+                playlist: playlist.to_owned(),
+                name: name.to_owned()
+            },
+            _ => return Err(CommandError::Usage(String::from("'playlist rename' requires a playlist and a new name")))
+        },
+        "delete" => match args[..] {
+            [playlist] => PlaylistCommand::Delete { playlist: playlist.to_owned() },
+            _ => return Err(CommandError::Usage(String::from("'playlist delete' requires a single playlist")))
+        },
+        "add" | "remove" => {
+            let (playlist, query) = args.split_first()
+                .ok_or_else(|| CommandError::Usage(format!("'playlist {}' requires a playlist", action)))?;
+
+            let playlist = playlist.to_string();
+            let target = parse_play_target(query)?;
+
+            if *action == "add" {
+                PlaylistCommand::Add { playlist, target }
+            } else {
+                PlaylistCommand::Remove { playlist, target }
+            }
+        },
+        _ => return Err(usage())
+    };
+
+    Ok(cmd)
+}
+
+fn parse_track_uri(rest: &[&str]) -> Result<Option<SpotifyUri>, CommandError> {
+    match rest {
+        [] => Ok(None),
+        [uri] => match SpotifyUri::parse(uri) {
+            Some(uri @ SpotifyUri::Track(_)) => Ok(Some(uri)),
+            _ => Err(CommandError::Usage(format!("'{}' is not a track URI or link", uri)))
+        },
+        _ => Err(CommandError::Usage(String::from("Expected a single track URI or link")))
+    }
+}
+
+fn parse_status_format(rest: &[&str]) -> Result<StatusFormat, CommandError> {
+    match rest {
+        [] => Ok(StatusFormat::Text),
+        ["--json"] => Ok(StatusFormat::Json),
+        ["--format", template] => Ok(StatusFormat::Template(template.to_string())),
+        ["--format"] => Err(CommandError::Usage(String::from("'--format' requires a template"))),
+        _ => Err(CommandError::Usage(String::from("Expected either '--json' or '--format <TEMPLATE>'")))
+    }
+}
+
+fn parse_play_target(rest: &[&str]) -> Result<PlayTarget, CommandError> {
+    let mut kind = None;
+    let mut query = Vec::new();
+    let mut choice = Choice::Best;
+
+    let mut args = rest.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--track" => kind = Some(SearchKind::Track),
+            "--album" => kind = Some(SearchKind::Album),
+            "--artist" => kind = Some(SearchKind::Artist),
+            "--playlist" => kind = Some(SearchKind::Playlist),
+            "--pick" => choice = Choice::Pick,
+            "--select" => {
+                let n = args.next()
+                    .and_then(|n| n.parse::<usize>().ok())
+                    .filter(|n| *n > 0)
+                    .ok_or_else(|| CommandError::Usage(String::from("'--select' requires a positive number")))?;
+
+                choice = Choice::Select(n);
+            },
+            arg => query.push(arg)
+        }
+    }
+
+    let query = query.join(" ");
+    if query.is_empty() {
+        return Err(CommandError::Usage(String::from("A URI or a search query is required")));
+    }
+
+    if kind.is_none() {
+        if let Some(uri) = SpotifyUri::parse(&query) {
+            return Ok(PlayTarget::Uri(uri));
+        }
+    }
+
+    Ok(PlayTarget::Search {
+        kind: kind.unwrap_or(SearchKind::Track),
+        query,
+        choice
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn splits_quoted_arguments() {
+        assert_eq!(split_args("play  --track 'bohemian rhapsody'").unwrap(), args(&["play", "--track", "bohemian rhapsody"]));
+        assert_eq!(split_args(r#"playlist rename "Old name" New"#).unwrap(), args(&["playlist", "rename", "Old name", "New"]));
+        assert_eq!(split_args(r#"say "it's" ''"#).unwrap(), args(&["say", "it's", ""]));
+        assert_eq!(split_args("   ").unwrap(), Vec::<String>::new());
+        assert!(matches!(split_args("play 'unterminated"), Err(CommandError::Usage(_))));
+    }
+
+    #[test]
+    fn parses_positions() {
+        assert_eq!(parse_position("90").unwrap(), Adjust::To(90_000));
+        assert_eq!(parse_position("1:30").unwrap(), Adjust::To(90_000));
+        assert_eq!(parse_position("+10").unwrap(), Adjust::By(10_000));
+        assert_eq!(parse_position("-1:05").unwrap(), Adjust::By(-65_000));
+
+        for invalid in ["", "abc", "1:60", "1:", "+", "1:2:3"] {
+            assert!(matches!(parse_position(invalid), Err(CommandError::Usage(_))), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn rejects_overflowing_positions() {
+        assert_eq!(parse_position("4294967").unwrap(), Adjust::To(4_294_967_000));
+        assert!(matches!(parse_position("4294968"), Err(CommandError::Usage(_))));
+        assert!(matches!(parse_position("+71582789:00"), Err(CommandError::Usage(_))));
+        assert!(matches!(parse_position("99999999999"), Err(CommandError::Usage(_))));
+    }
+
+    #[test]
+    fn parses_volumes() {
+        assert_eq!(parse_volume("0").unwrap(), Adjust::To(0));
+        assert_eq!(parse_volume("100").unwrap(), Adjust::To(100));
+        assert_eq!(parse_volume("+5").unwrap(), Adjust::By(5));
+        assert_eq!(parse_volume("-20").unwrap(), Adjust::By(-20));
+
+        for invalid in ["101", "-300", "loud", ""] {
+            assert!(matches!(parse_volume(invalid), Err(CommandError::Usage(_))), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn takes_the_profile_option() {
+        let mut list = args(&["--profile", "work", "status", "--json"]);
+        assert_eq!(take_profile(&mut list).unwrap(), Profile::new("work").unwrap());
+        assert_eq!(list, args(&["status", "--json"]));
+
+        let mut list = args(&["status"]);
+        assert_eq!(take_profile(&mut list).unwrap(), Profile::default());
+        assert_eq!(list, args(&["status"]));
+
+        assert!(matches!(take_profile(&mut args(&["status", "--profile"])), Err(CommandError::Usage(_))));
+        assert!(matches!(take_profile(&mut args(&["--profile", "../etc"])), Err(CommandError::Usage(_))));
+    }
+}
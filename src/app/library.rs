@@ -0,0 +1,92 @@
+use super::{App, AppResult};
+use crate::spotify::{
+    io::{self, Io, IoEvent},
+    uri::SpotifyUri
+};
+
+use anyhow::{Context, bail};
+use rspotify::{
+    prelude::{OAuthClient, Id},
+    model::{PlayableItem, SavedTrack, TrackId}
+};
+
+const PAGE_LIMIT: u32 = 50;
+
+impl App {
+    pub(super) async fn set_saved(&self, uri: Option<SpotifyUri>, saved: bool) -> AppResult<()> {
+        let id = match uri {
+            Some(SpotifyUri::Track(id)) => id,
+            Some(_) => bail!("Only tracks can be saved to Liked Songs"),
+            None => self.playing_track().await?
+        };
+
+        let event = if saved {
+            IoEvent::SaveTrack(id)
+        } else {
+            IoEvent::RemoveSavedTrack(id)
+        };
+
+        io::handle_event(event, &Io::default(), self).await
+            .context("Unable to update Liked Songs")?;
+
+        Ok(())
+    }
+
+    pub(super) async fn list_liked(&self, limit: u32, json: bool) -> AppResult<()> {
+        let client = &self.spotify.client();
+        let mut tracks: Vec<SavedTrack> = Vec::new();
+
+        while (tracks.len() as u32) < limit {
+            let page = client.current_user_saved_tracks_manual(
+                None,
+                Some(PAGE_LIMIT.min(limit - tracks.len() as u32)),
+                Some(tracks.len() as u32)
+            ).await.context("Unable to fetch Liked Songs")?;
+
+            let done = page.next.is_none() || page.items.is_empty();
+            tracks.extend(page.items);
+
+            if done {
+                break;
+            }
+        }
+
+        if json {
+            println!("{}", serde_json::to_string(&tracks)?);
+        } else {
+            for saved in &tracks {
+                let artists = saved.track.artists
+                    .iter()
+                    .map(|a| a.name.clone())
+                    .collect::<Vec<String>>()
+                    .join(", ");
+
+                println!(
+                    "{}  {:<60} {}",
+                    saved.added_at.format("%Y-%m-%d"),
+                    format!("{} - {}", artists, saved.track.name),
+                    saved.track.id.as_ref().map(|id| id.uri()).unwrap_or_default()
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn playing_track(&self) -> AppResult<TrackId> {
+        io::handle_event(IoEvent::FetchCurrentPlayback, &Io::default(), self).await
+            .context("Unable to fetch current playback")?;
+
+        let app_state = self.spotify.state.lock().await;
+        let item = app_state.playback
+            .as_ref()
+            .and_then(|p| p.item.as_ref());
+
+        match item {
+            Some(PlayableItem::Track(track)) => track.id.clone()
+                .context("The playing track is a local file"),
+            Some(PlayableItem::Episode(_)) => bail!("Episodes can't be saved to Liked Songs"),
+            None => bail!("Nothing is playing right now")
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use super::{App, AppResult};
+use crate::spotify::{
+    LoginState,
+    auth::{self, PendingLogin, refresh_if_expired},
+    callback::{CallbackServer, CALLBACK_TIMEOUT},
+    io::{self, IoEvent},
+    notification::Notification,
+    vault::Passphrase
+};
+
+use std::sync::Arc;
+use tokio::sync::mpsc::UnboundedSender;
+
+impl App {
+    // Shows the authorize URL in the login panel and waits for the redirect
+    pub(super) fn start_login(&self, sender: UnboundedSender<IoEvent>) {
+        let app = self.clone();
+
+        self.rt.spawn(async move {
+            let client = auth::with_granted_scopes(&app.spotify.client()).await;
+            app.spotify.set_client(client.clone());
+
+            let pending = match auth::begin_login(&client) {
+                Ok(pending) => Arc::new(pending),
+                Err(e) => {
+                    app.set_login_state(LoginState::Failed(format!("{:#}", e))).await;
+                    return;
+                }
+            };
+
+            {
+                let state = &mut app.spotify.state.lock().await;
+                state.login.cancel();
+                state.login = LoginState::Authorizing(pending.clone());
+            }
+
+            // The redirect URL can still be pasted when the port is taken
+            let server = match CallbackServer::bind(&client.oauth.redirect_uri, &client.oauth.state).await {
+                Ok(server) => server,
+                Err(e) => {
+                    *pending.callback_error.lock().unwrap() = Some(format!("{:#}", e));
+                    return;
+                }
+            };
+
+            let code = tokio::select! {
+                code = server.wait_for_code(CALLBACK_TIMEOUT) => code,
+                _ = pending.cancelled.notified() => return
+            };
+
+            app.finish_login(&pending, code, sender).await;
+        });
+    }
+
+    // Logs in again asking for the extra scopes on top of the granted ones
+    pub(super) fn request_scopes(&self, scopes: Vec<&'static str>, sender: UnboundedSender<IoEvent>) {
+        let granted = {
+            let state = &mut self.spotify.state.blocking_lock();
+            state.scope_request = None;
+            state.scopes.clone()
+        };
+
+        let requested = granted
+            .into_iter()
+            .chain(scopes.into_iter().map(String::from));
+
+        self.spotify.set_client(auth::with_scopes(&self.spotify.client(), requested));
+        self.start_login(sender);
+    }
+
+    pub(super) fn submit_redirect_url(&self, url: String, sender: UnboundedSender<IoEvent>) {
+        let app = self.clone();
+
+        self.rt.spawn(async move {
+            let pending = match &app.spotify.state.lock().await.login {
+                LoginState::Authorizing(pending) => pending.clone(),
+                _ => return
+            };
+
+            let code = auth::parse_code_input(&app.spotify.client(), &url);
+
+            app.finish_login(&pending, code, sender).await;
+        });
+    }
+
+    // Deleted plaintext caches are reported through the notifier
+    pub(super) fn unlock(
+        &self,
+        passphrase: Passphrase,
+        reset: bool,
+        sender: UnboundedSender<IoEvent>,
+        notifier: Option<UnboundedSender<Notification>>
+    ) {
+        let app = self.clone();
+
+        self.rt.spawn(async move {
+            let client = app.spotify.client();
+            let vault = app.spotify.vault();
+
+            let deleted = match reset {
+                true => vault.delete().map(|_| ()),
+                false => Ok(())
+            };
+
+            let unlocked = match deleted {
+                Ok(_) => vault.unlock(&passphrase.0).await,
+                Err(e) => Err(e)
+            };
+
+            app.spotify.state.lock().await.unlock_error = unlocked
+                .as_ref()
+                .err()
+                .map(|e| format!("{:#}", e));
+
+            let unlocked = match unlocked {
+                Ok(unlocked) => unlocked,
+                Err(_) => return
+            };
+
+            for notice in unlocked.notices() {
+                if let Some(notifier) = &notifier {
+                    let _ = notifier.send(Notification::info(notice));
+                }
+            }
+
+            match unlocked.token {
+                Some(token) => {
+                    *client.token.lock().await.unwrap() = Some(token);
+
+                    let result = refresh_if_expired(&client, &vault).await;
+                    app.logged_in(result, &sender).await;
+                },
+                // Nothing cached yet, log in from scratch
+                None => app.start_login(sender)
+            }
+        });
+    }
+
+    async fn finish_login(&self, pending: &Arc<PendingLogin>, code: AppResult<String>, sender: UnboundedSender<IoEvent>) {
+        // Ignore codes for an attempt that has been superseded
+        {
+            let state = &mut self.spotify.state.lock().await;
+
+            match &state.login {
+                LoginState::Authorizing(current) if Arc::ptr_eq(current, pending) => (),
+                _ => return
+            }
+
+            state.login.cancel();
+            state.login = LoginState::Exchanging;
+        }
+
+        let result = match code {
+            Ok(code) => {
+                let client = self.spotify.client();
+                auth::finish_login(&client, &self.spotify.vault(), pending, &code).await
+            },
+            Err(e) => Err(e)
+        };
+
+        self.logged_in(result, &sender).await;
+    }
+
+    async fn logged_in(&self, result: AppResult<()>, sender: &UnboundedSender<IoEvent>) {
+        match result {
+            Ok(_) => {
+                self.set_login_state(LoginState::LoggedIn).await;
+
+                let resumed = sender.send(IoEvent::Resume)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|_| io::fetch_all(sender));
+
+                if let Err(e) = resumed {
+                    eprintln!("Error in login: {}", e);
+                }
+            },
+            Err(e) => {
+                self.set_login_state(LoginState::Failed(format!("{:#}", e))).await;
+            }
+        }
+    }
+
+    async fn set_login_state(&self, login: LoginState) {
+        self.spotify.state.lock().await.login = login;
+    }
+}
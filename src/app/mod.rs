@@ -1,10 +1,14 @@
 pub use self::builder::AppBuilder;
+pub use self::command::{Command, ExitStatus};
+
+use self::ui::UiState;
 
 use crate::{
     spotify::{
-        Spotify,
-        io::{Io, IoState, IoEvent, self},
-        auth::oauth_client
+        Spotify, LoginState,
+        io::{Io, IoState, IoEvent, Shutdown, SHUTDOWN_TIMEOUT, self},
+        auth::{build_client, load_cached_token},
+        vault::Vault
     },
     system
 };
@@ -14,11 +18,21 @@ use tokio::{
     runtime::Runtime,
     sync::{Mutex, mpsc}
 };
-use anyhow::{Context, Result};
-use rspotify::prelude::OAuthClient;
+use anyhow::Result;
 
 mod builder;
+mod cli;
+mod library;
+mod login;
+mod playlist;
+mod script;
+mod shell;
 mod ui;
+mod watch;
+
+pub mod command;
+pub mod paths;
+pub mod status;
 
 pub type AppResult<T> = Result<T>;
 
@@ -26,6 +40,7 @@ pub type AppResult<T> = Result<T>;
 pub struct App {
     pub rt: Arc<Runtime>,
     pub cli: bool,
+    pub args: Vec<String>,
     pub spotify: Spotify
 }
 
@@ -34,80 +49,96 @@ impl App {
         AppBuilder::default()
     }
 
-    pub fn run(mut self) -> AppResult<()> {
-        // Authenticate Spotify client
-        self.spotify.client = self.rt.block_on(oauth_client())?;
-
-        if !self.cli {
-            // Initialize window system handler
-            let system = system::init(file!());
-
-            // Create state and channels for IO events
-            let io_state = Arc::new(Mutex::new(IoState::default()));
-            let (tx, rx) = mpsc::unbounded_channel();
-
-            // Run the IO thread
-            let io_handle = {
-                let a = self.clone();
-                let io = Io {
-                    state: io_state.clone(),
-                    receiver: Some(rx),
-                    sender: None
-                };
-
-                self.rt.spawn(async move {
-                    io::main_loop(io, a).await;
-                })
-            };
+    pub fn run(mut self) -> AppResult<ExitStatus> {
+        if self.cli {
+            return Ok(self.run_cli());
+        }
 
-            // Run the UI thread
-            {
-                let a = self.clone();
-                let io = Io {
-                    state: io_state,
-                    receiver: None,
-                    sender: Some(tx)
-                };
-
-                system.main_loop(move |s, r, u| {
-                    ui::main_loop(&io, &a, s, r, u);
-                });
+        // Use the cached token if there is one, logging in happens in the UI otherwise
+        let profile = self.spotify.profile();
+        let settings = profile.settings()?;
+        let vault = Vault::for_profile(&profile)?;
+        let client = build_client(&profile, &vault)?;
+
+        let login = match vault.is_enabled() {
+            true => LoginState::LoggedOut,
+            false => match self.rt.block_on(load_cached_token(&client, &vault)) {
+                Ok(true) => LoginState::LoggedIn,
+                Ok(false) => LoginState::LoggedOut,
+                Err(e) => LoginState::Failed(format!("{:#}", e))
             }
+        };
+
+        let logged_out = matches!(login, LoginState::LoggedOut) && !vault.is_enabled();
+        let logged_in = matches!(login, LoginState::LoggedIn);
+
+        self.spotify.set_client(client);
+        self.spotify.set_vault(vault);
+        self.spotify.state.blocking_lock().login = login;
+
+        // Initialize window system handler
+        let system = system::init(file!());
+
+        // Create state and channels for IO events
+        let io_state = Arc::new(Mutex::new(IoState {
+            polling: settings.polling,
+            ..Default::default()
+        }));
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (notifier, notifications) = mpsc::unbounded_channel();
+        let shutdown = Shutdown::default();
 
-            // Gracefully exit the IO thread
-            self.rt.block_on(io_handle)?;
+        if logged_in {
+            io::fetch_all(&tx)?;
+        } else if logged_out {
+            self.start_login(tx.clone());
         }
 
-        else {
-            let user = self.rt.block_on(self.spotify.client.me())?;
-            println!("Logged-in as: {}", user.display_name.unwrap_or(String::new()));
+        // Run the IO thread
+        let io_handle = {
+            let a = self.clone();
+            let io = Io {
+                state: io_state.clone(),
+                receiver: Some(rx),
+                sender: Some(tx.clone()),
+                notifier: Some(notifier),
+                shutdown: shutdown.clone()
+            };
+
+            self.rt.spawn(io::main_loop(io, a))
+        };
+
+        // Run the UI thread
+        {
+            let a = self.clone();
+            let io = Io {
+                state: io_state,
+                receiver: None,
+                sender: Some(tx),
+                notifier: None,
+                shutdown: shutdown.clone()
+            };
 
-            let cmd = std::env::args()
-                .nth(1)
-                .context("Invalid argument")?;
+            let mut ui_state = UiState::new(notifications);
 
-            // Parse and handle commands
-            self.rt.block_on(self.handle_command(cmd.as_str()))?;
+            system.main_loop(move |s, r, u| {
+                ui::main_loop(&io, &a, &mut ui_state, s, r, u);
+            });
         }
 
-        Ok(())
-    }
+        // Gracefully exit the IO thread
+        shutdown.request();
 
-    async fn handle_command(&self, cmd: &str) -> AppResult<()> {
-        let client = &self.spotify.client;
+        let stopped = self.rt.block_on(async {
+            tokio::time::timeout(SHUTDOWN_TIMEOUT, io_handle).await
+        });
 
-        match cmd {
-            "--resume" => {
-                client.resume_playback(None, None).await
-                    .context("Unable to resume playback")?;
-            }
-            "--pause" => {
-                client.pause_playback(None).await
-                    .context("Unable to pause playback")?
-            }
-            _ => todo!()
-        };
+        match stopped {
+            Ok(result) => result?,
+            Err(_) => eprintln!("The IO thread didn't stop in time")
+        }
 
-        Ok(())
+        Ok(ExitStatus::Success)
     }
 }
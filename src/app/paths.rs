@@ -0,0 +1,83 @@
+use super::AppResult;
+
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf}
+};
+use anyhow::Context;
+
+const APP_DIR: &str = "imspotify";
+
+pub fn data_dir() -> AppResult<PathBuf> {
+    let dir = dirs::data_dir()
+        .context("Unable to locate the user data directory")?
+        .join(APP_DIR);
+
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Unable to create directory {}", dir.display()))?;
+
+    Ok(dir)
+}
+
+pub fn history_file() -> AppResult<PathBuf> {
+    Ok(data_dir()?.join("history"))
+}
+
+// Creates the directory if needed and makes it accessible only to the current user
+pub fn create_private_dir(dir: &Path) -> AppResult<()> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Unable to create directory {}", dir.display()))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+    }
+
+    Ok(())
+}
+
+// Creates the file if needed and makes it readable only by the current user
+pub fn create_private_file(path: &Path) -> AppResult<()> {
+    let mut options = fs::OpenOptions::new();
+    options.create(true).append(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+        options.mode(0o600);
+        options.open(path)
+            .with_context(|| format!("Unable to create file {}", path.display()))?;
+
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+
+    #[cfg(not(unix))]
+    options.open(path)
+        .with_context(|| format!("Unable to create file {}", path.display()))?;
+
+    Ok(())
+}
+
+// Overwrites the file before deleting it, so its contents don't linger on disk
+pub fn remove_file_securely(path: &Path) -> AppResult<()> {
+    let len = fs::metadata(path)
+        .with_context(|| format!("Unable to read {}", path.display()))?
+        .len();
+
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .with_context(|| format!("Unable to open {}", path.display()))?;
+
+    file.write_all(&vec![0u8; len as usize])?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::remove_file(path)
+        .with_context(|| format!("Unable to delete {}", path.display()))?;
+
+    Ok(())
+}
@@ -0,0 +1,154 @@
+use super::{
+    App,
+    AppResult,
+    command::PlaylistCommand
+};
+use crate::spotify::{
+    item_title,
+    io::{self, Io, IoEvent},
+    uri::SpotifyUri
+};
+
+use anyhow::{Context, bail};
+use rspotify::{
+    prelude::{BaseClient, OAuthClient, Id},
+    model::{PlayableItem, PlaylistId, SimplifiedPlaylist}
+};
+
+impl App {
+    pub(super) async fn list_playlists(&self, json: bool) -> AppResult<()> {
+        io::handle_event(IoEvent::FetchPlaylists, &Io::default(), self).await
+            .context("Unable to fetch playlists")?;
+
+        let app_state = self.spotify.state.lock().await;
+        let playlists = app_state.playlists.as_deref().unwrap_or_default();
+
+        if json {
+            println!("{}", serde_json::to_string(playlists)?);
+        } else {
+            for playlist in playlists {
+                println!(
+                    "{:<40} {:>6}  {:<20} {}",
+                    playlist.name,
+                    playlist.tracks.total,
+                    playlist.owner.display_name.as_deref().unwrap_or_default(),
+                    playlist.id.id()
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(super) async fn handle_playlist_command(&self, cmd: PlaylistCommand) -> AppResult<()> {
+        let client = &self.spotify.client();
+
+        match cmd {
+            PlaylistCommand::Show { playlist, limit, offset, json } => {
+                let id = self.resolve_playlist(&playlist).await?;
+                let page = client.playlist_items_manual(&id, None, None, Some(limit), Some(offset)).await
+                    .context("Unable to fetch playlist items")?;
+
+                if json {
+                    println!("{}", serde_json::to_string(&page)?);
+                } else {
+                    for (i, item) in page.items.iter().enumerate() {
+                        let (title, uri) = match &item.track {
+                            Some(track) => (item_title(track), playable_uri(track)),
+                            None => (String::from("(unavailable)"), String::new())
+                        };
+
+                        println!("{:>5}. {:<60} {}", offset as usize + i + 1, title, uri);
+                    }
+
+                    println!(
+                        "Showing {}-{} of {}",
+                        (offset + 1).min(page.total),
+                        offset as usize + page.items.len(),
+                        page.total
+                    );
+                }
+            },
+            PlaylistCommand::Create { name, public, description } => {
+                let user = client.me().await?;
+                let playlist = client.user_playlist_create(
+                    &user.id,
+                    &name,
+                    Some(public),
+                    None,
+                    description.as_deref()
+                ).await.context("Unable to create playlist")?;
+
+                println!("Created playlist '{}' ({})", playlist.name, playlist.id.uri());
+            },
+            PlaylistCommand::Rename { playlist, name } => {
+                let id = self.resolve_playlist(&playlist).await?;
+
+                client.playlist_change_detail(&id, Some(&name), None, None, None).await
+                    .context("Unable to rename playlist")?;
+            },
+            PlaylistCommand::Delete { playlist } => {
+                let id = self.resolve_playlist(&playlist).await?;
+
+                client.playlist_unfollow(&id).await
+                    .context("Unable to delete playlist")?;
+            },
+            PlaylistCommand::Add { playlist, target } => {
+                let id = self.resolve_playlist(&playlist).await?;
+                let uri = self.resolve_target(target).await?;
+                let item = uri.as_playable()
+                    .context("Only tracks and episodes can be added to a playlist")?;
+
+                client.playlist_add_items(&id, [item], None).await
+                    .context("Unable to add to the playlist")?;
+            },
+            PlaylistCommand::Remove { playlist, target } => {
+                let id = self.resolve_playlist(&playlist).await?;
+                let uri = self.resolve_target(target).await?;
+                let item = uri.as_playable()
+                    .context("Only tracks and episodes can be removed from a playlist")?;
+
+                client.playlist_remove_all_occurrences_of_items(&id, [item], None).await
+                    .context("Unable to remove from the playlist")?;
+            }
+        };
+
+        Ok(())
+    }
+
+    // Accepts a playlist URI, link, name of one of the user's playlists or a raw id
+    async fn resolve_playlist(&self, playlist: &str) -> AppResult<PlaylistId> {
+        if let Some(SpotifyUri::Playlist(id)) = SpotifyUri::parse(playlist) {
+            return Ok(id);
+        }
+
+        io::handle_event(IoEvent::FetchPlaylists, &Io::default(), self).await
+            .context("Unable to fetch playlists")?;
+
+        let app_state = self.spotify.state.lock().await;
+        let matches = app_state.playlists
+            .iter()
+            .flatten()
+            .filter(|p| p.name.eq_ignore_ascii_case(playlist))
+            .collect::<Vec<&SimplifiedPlaylist>>();
+
+        match &matches[..] {
+            [p] => Ok(p.id.clone()),
+            [] => match PlaylistId::from_id(playlist) {
+                Ok(id) if playlist.len() == 22 => Ok(id),
+                _ => bail!("No playlist named '{}'", playlist)
+            },
+            _ => bail!("Multiple playlists are named '{}', please use the playlist id", playlist)
+        }
+    }
+}
+
+fn playable_uri(item: &PlayableItem) -> String {
+    match item {
+        PlayableItem::Track(t) => t.id
+            .as_ref()
+            .map(|id| id.uri())
+            .unwrap_or_default(),
+        PlayableItem::Episode(e) => e.id.uri()
+    }
+}
@@ -0,0 +1,186 @@
+use super::{
+    App,
+    AppResult,
+    command::{Command, split_args},
+    status::PlaybackStatus
+};
+use crate::spotify::{
+    auth::{authenticate, refresh_if_expired},
+    io::PLAYBACK_POLL_INTERVAL
+};
+
+use std::{
+    io::Read,
+    path::Path,
+    time::Duration
+};
+use anyhow::{Context, bail, anyhow};
+use tokio::time::sleep;
+use rspotify::{
+    prelude::OAuthClient,
+    model::AdditionalType
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ErrorPolicy {
+    Continue,
+    Abort
+}
+
+#[derive(Debug)]
+enum Step {
+    Sleep(Duration),
+    WaitUntilTrackEnd,
+    OnError(ErrorPolicy),
+    Command(Command)
+}
+
+impl App {
+    // Reads the script from the given file, or from stdin when no file is given
+    pub(super) async fn run_script(&self, path: Option<&Path>) -> AppResult<()> {
+        let source = match path {
+            Some(path) => std::fs::read_to_string(path)
+                .with_context(|| format!("Unable to read script {}", path.display()))?,
+            None => {
+                let mut source = String::new();
+                std::io::stdin().read_to_string(&mut source)
+                    .context("Unable to read script from stdin")?;
+                source
+            }
+        };
+
+        let steps = parse_script(&source)?;
+
+        let mut policy = ErrorPolicy::Abort;
+        let mut failed = 0;
+
+        for (line, step) in steps {
+            let result = match step {
+                Step::Sleep(duration) => {
+                    sleep(duration).await;
+                    Ok(())
+                },
+                Step::WaitUntilTrackEnd => self.wait_until_track_end().await,
+                Step::OnError(p) => {
+                    policy = p;
+                    Ok(())
+                },
+                Step::Command(cmd) => self.handle_command(cmd).await
+            };
+
+            if let Err(e) = result {
+                eprintln!("Error on line {}: {:#}", line, e);
+                failed += 1;
+
+                if policy == ErrorPolicy::Abort {
+                    bail!("Script aborted on line {}", line);
+                }
+            }
+        }
+
+        if failed > 0 {
+            bail!("{} line(s) of the script failed", failed);
+        }
+
+        Ok(())
+    }
+
+    async fn wait_until_track_end(&self) -> AppResult<()> {
+        let initial = match self.playback_status().await? {
+            Some(initial) => initial,
+            None => return Ok(())
+        };
+
+        let mut current = initial.clone();
+
+        loop {
+            let wait = if current.is_playing {
+                let remaining = current.duration_ms.saturating_sub(current.progress_ms) as u64;
+                Duration::from_millis(remaining) + Duration::from_millis(500)
+            } else {
+                PLAYBACK_POLL_INTERVAL
+            };
+
+            sleep(wait).await;
+
+            current = match self.playback_status().await? {
+                Some(current) if current.uri == initial.uri && current.track == initial.track => current,
+                _ => return Ok(())
+            };
+        }
+    }
+
+    // None once nothing is playing anymore. The token can expire while
+    // waiting for a long track, so it's refreshed before every fetch.
+    async fn playback_status(&self) -> AppResult<Option<PlaybackStatus>> {
+        let client = &self.spotify.client();
+        let vault = self.spotify.vault();
+
+        if vault.needs_unlock() {
+            authenticate(client, &vault).await?;
+        }
+
+        refresh_if_expired(client, &vault).await?;
+
+        let playback = client.current_playback(
+            None,
+            Some(vec![&AdditionalType::Episode, &AdditionalType::Track])
+        ).await.context("Unable to fetch current playback")?;
+
+        Ok(playback.as_ref().and_then(PlaybackStatus::new))
+    }
+}
+
+fn parse_script(source: &str) -> AppResult<Vec<(usize, Step)>> {
+    let mut steps = Vec::new();
+    let mut errors = Vec::new();
+
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        match parse_step(line) {
+            Ok(step) => steps.push((line_number, step)),
+            Err(e) => errors.push(format!("line {}: {}", line_number, e))
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(anyhow!("Invalid script:\n  {}", errors.join("\n  ")));
+    }
+
+    Ok(steps)
+}
+
+fn parse_step(line: &str) -> AppResult<Step> {
+    let args = split_args(line)?;
+
+    let step = match args.iter().map(|a| a.as_str()).collect::<Vec<&str>>()[..] {
+        ["sleep", secs] => {
+            let secs = secs.parse::<f64>()
+                .ok()
+                .filter(|s| s.is_finite() && *s >= 0.0)
+                .with_context(|| format!("Invalid duration '{}'", secs))?;
+
+            Step::Sleep(Duration::from_secs_f64(secs))
+        },
+        ["sleep", ..] => bail!("Expected 'sleep <SECS>'"),
+        ["wait-until-track-end"] => Step::WaitUntilTrackEnd,
+        ["on-error", "continue"] => Step::OnError(ErrorPolicy::Continue),
+        ["on-error", "abort"] => Step::OnError(ErrorPolicy::Abort),
+        ["on-error", ..] => bail!("Expected 'on-error continue' or 'on-error abort'"),
+        _ => match Command::parse(&args)? {
+            // Watching never ends by itself
+            Command::Shell | Command::Run(_) | Command::Login { .. } | Command::Watch { .. } => {
+                bail!("'{}' can't be used in a script", args[0])
+            },
+            cmd => Step::Command(cmd)
+        }
+    };
+
+    Ok(step)
+}
@@ -0,0 +1,194 @@
+use super::{
+    App,
+    AppResult,
+    paths,
+    command::{Command, COMMANDS, split_args}
+};
+use crate::spotify::{
+    Spotify,
+    io::{self, Io, IoEvent}
+};
+
+use rustyline::{
+    Context,
+    Editor,
+    Helper,
+    completion::{Completer, Pair},
+    error::ReadlineError,
+    highlight::Highlighter,
+    hint::Hinter,
+    validate::Validator
+};
+
+const PROMPT: &str = "imspotify> ";
+
+struct ShellHelper {
+    spotify: Spotify
+}
+
+impl App {
+    pub(super) fn run_shell(&self) -> AppResult<()> {
+        // Prefetch names used for tab completion
+        self.rt.block_on(async {
+            let io = Io::default();
+
+            for event in [IoEvent::FetchPlaylists, IoEvent::FetchDevices] {
+                if let Err(e) = io::handle_event(event, &io, self).await {
+                    eprintln!("Error: {:#}", e);
+                }
+            }
+        });
+
+        let mut editor = Editor::<ShellHelper>::new()?;
+        editor.set_helper(Some(ShellHelper {
+            spotify: self.spotify.clone()
+        }));
+
+        let history = paths::history_file()?;
+        let _ = editor.load_history(&history);
+
+        loop {
+            let line = match editor.readline(PROMPT) {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => break,
+                Err(e) => return Err(e.into())
+            };
+
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            editor.add_history_entry(line);
+
+            // Saved right away, Ctrl-C while watching ends the whole process
+            if let Err(e) = editor.save_history(&history) {
+                eprintln!("Unable to save the shell history: {}", e);
+            }
+
+            let args = match split_args(line) {
+                Ok(args) => args,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    continue;
+                }
+            };
+
+            if matches!(args[0].as_str(), "exit" | "quit") {
+                break;
+            }
+
+            match Command::parse(&args) {
+                Ok(Command::Shell) => eprintln!("Error: The shell is already running"),
+                Ok(Command::Run(None)) => eprintln!("Error: A script file is required in the shell"),
+                Ok(Command::Run(Some(path))) => {
+                    if let Err(e) = self.rt.block_on(self.run_script(Some(&path))) {
+                        eprintln!("Error: {:#}", e);
+                    }
+                },
+                Ok(cmd) => {
+                    if let Err(e) = self.rt.block_on(self.handle_command(cmd)) {
+                        eprintln!("Error: {:#}", e);
+                    }
+                },
+                Err(e) => eprintln!("Error: {}", e)
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl ShellHelper {
+    fn candidates(&self, args: &[String]) -> Vec<String> {
+        let app_state = self.spotify.state.blocking_lock();
+
+        let playlists = || -> Vec<String> {
+            app_state.playlists
+                .iter()
+                .flatten()
+                .map(|p| p.name.clone())
+                .collect()
+        };
+
+        let devices = || -> Vec<String> {
+            app_state.devices
+                .iter()
+                .flatten()
+                .map(|d| d.name.clone())
+                .collect()
+        };
+
+        match args.iter().map(|a| a.as_str()).collect::<Vec<&str>>()[..] {
+            [] => COMMANDS
+                .iter()
+                .map(|c| c.to_string())
+                .chain([String::from("exit")])
+                .collect(),
+            ["transfer"] => devices(),
+            ["playlist", "show" | "rename" | "delete" | "add" | "remove"] => playlists(),
+            [.., "--playlist"] => playlists(),
+            _ => Vec::new()
+        }
+    }
+}
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let line = &line[..pos];
+        let start = word_start(line);
+
+        let (args, word) = line.split_at(start);
+        let args = split_args(args).unwrap_or_default();
+        let word = word.trim_start_matches(|c| c == '"' || c == '\'');
+
+        let pairs = self.candidates(&args)
+            .into_iter()
+            .filter(|c| c.to_lowercase().starts_with(&word.to_lowercase()))
+            .map(|c| Pair {
+                replacement: if c.contains(char::is_whitespace) {
+                    format!("\"{}\"", c)
+                } else {
+                    c.clone()
+                },
+                display: c
+            })
+            .collect();
+
+        Ok((start, pairs))
+    }
+}
+
+// Finds where the word under the cursor begins, treating an open quote as its start
+fn word_start(line: &str) -> usize {
+    let mut start = 0;
+    let mut quote = None;
+
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => (),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                start = i;
+            },
+            (None, c) if c.is_whitespace() => start = i + c.len_utf8(),
+            (None, _) => ()
+        }
+    }
+
+    start
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
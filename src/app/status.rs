@@ -0,0 +1,141 @@
+use super::ui::format_millis;
+
+use serde::Serialize;
+use rspotify::{
+    prelude::Id,
+    model::{
+        CurrentPlaybackContext,
+        PlayableItem,
+        RepeatState
+    }
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StatusFormat {
+    Text,
+    Json,
+    Template(String)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlaybackStatus {
+    pub uri: Option<String>,
+    pub track: String,
+    pub artists: Vec<String>,
+    pub album: String,
+    pub progress_ms: u128,
+    pub duration_ms: u128,
+    pub device: String,
+    pub device_id: Option<String>,
+    pub volume_percent: Option<u32>,
+    pub shuffle: bool,
+    pub repeat: RepeatState,
+    pub is_playing: bool
+}
+
+impl PlaybackStatus {
+    pub fn new(playback: &CurrentPlaybackContext) -> Option<Self> {
+        let (uri, track, artists, album, duration) = match playback.item.as_ref()? {
+            PlayableItem::Track(t) => (
+                t.id.as_ref().map(|id| id.uri()),
+                t.name.clone(),
+                t.artists.iter()
+                    .map(|a| a.name.clone())
+                    .collect::<Vec<String>>(),
+                t.album.name.clone(),
+                t.duration.as_millis()
+            ),
+            PlayableItem::Episode(e) => (
+                Some(e.id.uri()),
+                e.name.clone(),
+                vec![e.show.name.clone()],
+                e.show.name.clone(),
+                e.duration.as_millis()
+            )
+        };
+
+        Some(Self {
+            uri,
+            track,
+            artists,
+            album,
+            progress_ms: playback.progress.unwrap_or_default().as_millis(),
+            duration_ms: duration,
+            device: playback.device.name.clone(),
+            device_id: playback.device.id.clone(),
+            volume_percent: playback.device.volume_percent,
+            shuffle: playback.shuffle_state,
+            repeat: playback.repeat_state,
+            is_playing: playback.is_playing
+        })
+    }
+
+    pub fn render(&self, format: &StatusFormat) -> String {
+        match format {
+            StatusFormat::Text => self.render_template(
+                "{state}: {artist} - {title}\n\
+                Album: {album}\n\
+                Progress: {progress} / {duration}\n\
+                Device: {device}\n\
+                Shuffle: {shuffle}, Repeat: {repeat}"
+            ),
+            StatusFormat::Json => serde_json::to_string(self)
+                .unwrap_or_default(),
+            StatusFormat::Template(template) => self.render_template(template)
+        }
+    }
+
+    pub fn render_template(&self, template: &str) -> String {
+        let mut output = String::with_capacity(template.len());
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            output.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            let value = rest.find('}').and_then(|end| {
+                self.field(&rest[1..end]).map(|value| (value, end))
+            });
+
+            match value {
+                Some((value, end)) => {
+                    output.push_str(&value);
+                    rest = &rest[end + 1..];
+                },
+                None => {
+                    output.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+
+        output.push_str(rest);
+        output
+    }
+
+    fn field(&self, name: &str) -> Option<String> {
+        let value = match name {
+            "title" | "track" => self.track.clone(),
+            "artist" | "artists" => self.artists.join(", "),
+            "album" => self.album.clone(),
+            "progress" => format_millis(self.progress_ms),
+            "duration" => format_millis(self.duration_ms),
+            "device" => self.device.clone(),
+            "volume" => self.volume_percent
+                .map(|v| v.to_string())
+                .unwrap_or_default(),
+            "uri" => self.uri.clone().unwrap_or_default(),
+            "shuffle" => String::from(if self.shuffle { "on" } else { "off" }),
+            "repeat" => String::from(match self.repeat {
+                RepeatState::Off => "off",
+                RepeatState::Track => "track",
+                RepeatState::Context => "context"
+            }),
+            "state" => String::from(if self.is_playing { "Playing" } else { "Paused" }),
+            "is_playing" => self.is_playing.to_string(),
+            _ => return None
+        };
+
+        Some(value)
+    }
+}
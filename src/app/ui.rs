@@ -1,22 +1,43 @@
 use crate::System;
-use crate::spotify::io::Io;
+use crate::spotify::{
+    LoginState, LoadProgress,
+    auth::TokenRevoked,
+    notification::{Notification, ErrorKind},
+    scopes,
+    io::Io,
+    profile::Profile,
+    uri::SpotifyUri,
+    vault::Passphrase,
+    item_title
+};
 
 use super::App;
 use super::IoEvent;
 
-use std::time::Duration;
-use rspotify::model::{
-    PlayableItem,
-    SimplifiedPlaylist
+use std::{collections::HashSet, time::Duration};
+use tokio::sync::mpsc::UnboundedReceiver;
+use rspotify::{
+    prelude::Id,
+    model::{
+        PlayableItem,
+        SimplifiedPlaylist
+    }
 };
 use tokio::time::Instant;
 use imgui::{
     Window,
     Ui,
+    ComboBox,
+    Condition,
     DockNode,
+    MenuItem,
+    MouseButton,
+    PopupModal,
     ProgressBar,
     Selectable,
     StyleColor,
+    StyleStackToken,
+    StyleVar,
     ImString,
     im_str,
     sys::{
@@ -26,18 +47,94 @@ use imgui::{
     }
 };
 
-pub fn main_loop(io: &Io, app: &App, system: &System, run: &mut bool, ui: &mut Ui) {
+const PASSPHRASE_CAPACITY: usize = 256;
+const REDIRECT_URL_CAPACITY: usize = 2048;
+
+const LOG_CAPACITY: usize = 200;
+const TOAST_DURATION: Duration = Duration::from_secs(6);
+const MAX_TOASTS: usize = 3;
+
+// State kept between frames
+pub struct UiState {
+    passphrase: ImString,
+    passphrase_confirm: ImString,
+    redirect_url: ImString,
+    // Along with the URL it failed to open
+    browser_error: Option<(String, String)>,
+    notifications: UnboundedReceiver<Notification>,
+    // Newest last
+    log: Vec<LogEntry>,
+    focused: bool
+}
+
+struct LogEntry {
+    notification: Notification,
+    // Repeats of the same error are counted instead of logged again
+    count: usize,
+    dismissed: bool
+}
+
+impl UiState {
+    pub fn new(notifications: UnboundedReceiver<Notification>) -> Self {
+        Self {
+            passphrase: ImString::with_capacity(PASSPHRASE_CAPACITY),
+            passphrase_confirm: ImString::with_capacity(PASSPHRASE_CAPACITY),
+            redirect_url: ImString::with_capacity(REDIRECT_URL_CAPACITY),
+            browser_error: None,
+            notifications,
+            log: Vec::new(),
+            focused: true
+        }
+    }
+
+    fn receive_notifications(&mut self) {
+        while let Ok(notification) = self.notifications.try_recv() {
+            match self.log.last_mut() {
+                Some(last) if last.notification.kind == notification.kind
+                    && last.notification.message == notification.message => {
+                    last.count += 1;
+                    last.dismissed = false;
+                    last.notification = notification;
+                },
+                _ => self.log.push(LogEntry { notification, count: 1, dismissed: false })
+            }
+        }
+
+        if self.log.len() > LOG_CAPACITY {
+            self.log.drain(..self.log.len() - LOG_CAPACITY);
+        }
+    }
+}
+
+pub fn main_loop(io: &Io, app: &App, state: &mut UiState, system: &System, run: &mut bool, ui: &mut Ui) {
+    state.receive_notifications();
+
+    // Playback is polled less often while the window isn't focused
+    if state.focused != system.focused {
+        state.focused = system.focused;
+
+        let io_state = &mut io.state.blocking_lock();
+        io_state.unfocused = !system.focused;
+        io_state.playback_refresh.notify_one();
+    }
+
     let dock_id = draw_dock();
 
     if system.first_run {
         dock_layout(dock_id, ui);
-        fetch_init_state(io);
     }
 
     draw_playlists(io, app, ui);
     draw_tracks(io, app, ui);
-    draw_properties(app, ui);
+    draw_properties(io, app, ui);
     draw_playback(io, app, ui);
+    draw_devices(io, app, ui);
+    draw_queue(io, app, ui);
+    draw_log(io, state, ui);
+    draw_permissions(io, app, ui);
+    draw_login(io, app, state, ui);
+    draw_unlock(io, app, state, ui);
+    draw_toasts(io, state, ui);
 
     *run = true;
 }
@@ -51,7 +148,7 @@ fn dock_layout(dock_id: u32, ui: &mut Ui) {
         |left| {
             left.split(
                 imgui::Direction::Down,
-                0.05_f32,
+                0.15_f32,
                 |down| {
                     down.dock_window(im_str!("Properties"));
                 },
@@ -65,23 +162,35 @@ fn dock_layout(dock_id: u32, ui: &mut Ui) {
                 imgui::Direction::Down,
                 0.2_f32,
                 |down| {
-                    down.dock_window(im_str!("Playback"));
+                    down.split(
+                        imgui::Direction::Right,
+                        0.3_f32,
+                        |right| {
+                            right.dock_window(im_str!("Devices"));
+                            right.dock_window(im_str!("Log"));
+                        },
+                        |left| {
+                            left.dock_window(im_str!("Playback"));
+                        }
+                    );
                 },
                 |up| {
-                    up.dock_window(im_str!("Tracks"));
+                    up.split(
+                        imgui::Direction::Right,
+                        0.3_f32,
+                        |right| {
+                            right.dock_window(im_str!("Queue"));
+                        },
+                        |left| {
+                            left.dock_window(im_str!("Tracks"));
+                        }
+                    );
                 }
             );
         },
     );
 }
 
-fn fetch_init_state(io: &Io) {
-    let sender = io.sender.as_ref().unwrap();
-    sender.send(IoEvent::FetchUserInfo).unwrap();
-    sender.send(IoEvent::FetchPlaylists).unwrap();
-    sender.send(IoEvent::FetchCurrentPlayback).unwrap();
-}
-
 fn draw_dock() -> u32 {
     unsafe {
         sys::igDockSpaceOverViewport(
@@ -100,6 +209,10 @@ fn draw_playlists(io: &Io, app: &App, ui: &mut Ui) {
 
         let mut selected_playlist: Option<SimplifiedPlaylist> = None;
 
+        if let Some(progress) = &app_state.playlists_progress {
+            draw_load_progress(progress, ui);
+        }
+
         if let Some(playlists) = &app_state.playlists {
             for playlist in playlists {
                 let mut selected: bool = false;
@@ -122,15 +235,21 @@ fn draw_playlists(io: &Io, app: &App, ui: &mut Ui) {
                 }
 
                 if selected {
-                    let playlist_id = playlist.id.clone();
                     selected_playlist = Some(playlist.clone());
-                    sender.send(IoEvent::FetchPlaylistItems(playlist_id)).unwrap();
                 }
             }
         }
 
+        // Items of the previous selection are dropped, even if they're still loading
         if let Some(selected) = selected_playlist {
+            let generation = app.spotify.playlist_items_generation.next();
+            let playlist_id = selected.id.clone();
+
             app_state.selected_playlist = Some(selected);
+            app_state.selected_playlist_items = None;
+            app_state.playlist_items_progress = Some(LoadProgress::default());
+
+            sender.send(IoEvent::FetchPlaylistItems(playlist_id, generation)).unwrap();
         }
     });
 }
@@ -140,6 +259,10 @@ fn draw_tracks(io: &Io, app: &App, ui: &mut Ui) {
         let sender = io.sender.as_ref().unwrap();
         let app_state = app.spotify.state.blocking_lock();
 
+        if let Some(progress) = &app_state.playlist_items_progress {
+            draw_load_progress(progress, ui);
+        }
+
         if let Some(items) = &app_state.selected_playlist_items {
             let items = items
                 .iter()
@@ -162,6 +285,28 @@ fn draw_tracks(io: &Io, app: &App, ui: &mut Ui) {
 
                     stack.pop(ui);
 
+                    if let Some(id) = &track.id {
+                        let popup_id = ImString::new(format!("track_menu##{}", id.id()));
+
+                        if ui.is_item_clicked(MouseButton::Right) {
+                            ui.open_popup(&popup_id);
+                        }
+
+                        ui.popup(&popup_id, || {
+                            let dimmed = dim_unless_granted(ui, &app_state.scopes, &[scopes::MODIFY_PLAYBACK]);
+
+                            if MenuItem::new(im_str!("Add to queue")).build(ui) {
+                                let uri = SpotifyUri::Track(id.clone());
+                                sender.send(IoEvent::AddToQueue(uri)).unwrap();
+                                sender.send(IoEvent::FetchQueue).unwrap();
+                            }
+
+                            if let Some(dimmed) = dimmed {
+                                dimmed.pop(ui);
+                            }
+                        });
+                    }
+
                     let stack = {
                         app_state.playback.as_ref().and_then(|playback| {
                             if let Some(PlayableItem::Track(playback_track)) = &playback.item {
@@ -194,22 +339,294 @@ fn draw_tracks(io: &Io, app: &App, ui: &mut Ui) {
     });
 }
 
-fn draw_properties(app: &App, ui: &mut Ui) {
+fn draw_properties(io: &Io, app: &App, ui: &mut Ui) {
     Window::new(im_str!("Properties")).build(ui, || {
-        let app_state = app.spotify.state.blocking_lock();
+        let sender = io.sender.as_ref().unwrap();
+        let mut app_state = app.spotify.state.blocking_lock();
 
         if let Some(me) = &app_state.me {
             ui.text(format!(
                 "Logged-in as: {}",
                 me.display_name.to_owned().unwrap_or(String::new())
             ));
+
+            if ui.button(im_str!("Log out"), [0.0, 0.0]) {
+                sender.send(IoEvent::Logout).unwrap();
+            }
         };
+
+        let active = app.spotify.profile();
+        let preview = ImString::new(&active.name);
+
+        ComboBox::new(im_str!("Profile"))
+            .preview_value(&preview)
+            .build(ui, || {
+                for profile in Profile::list().unwrap_or_default() {
+                    let selected = profile == active;
+
+                    if Selectable::new(&ImString::new(&profile.name)[..]).selected(selected).build(ui) && !selected {
+                        sender.send(IoEvent::SwitchProfile(profile)).unwrap();
+                    }
+                }
+            });
+
+        if app_state.me.is_none() {
+            return;
+        }
+
+        ui.separator();
+        ui.text("Permissions");
+
+        let mut scope_request = None;
+
+        for scope in scopes::OPTIONAL {
+            if app_state.scopes.contains(*scope) {
+                ui.text(format!("Can {}", scopes::describe(scope)));
+                continue;
+            }
+
+            ui.text_disabled(format!("Can't {}", scopes::describe(scope)));
+            ui.same_line(0.0);
+
+            if ui.small_button(&ImString::new(format!("Grant##{}", scope))) {
+                scope_request = Some(vec![*scope]);
+            }
+        }
+
+        if scope_request.is_some() {
+            app_state.scope_request = scope_request;
+        }
+    });
+}
+
+// Scopes requested by an action are authorized from here, the action is replayed afterwards
+fn draw_permissions(io: &Io, app: &App, ui: &mut Ui) {
+    let requested = match &app.spotify.state.blocking_lock().scope_request {
+        Some(requested) => requested.clone(),
+        None => return
+    };
+
+    let display_size = ui.io().display_size;
+
+    Window::new(im_str!("Permission required"))
+        .position([display_size[0] / 2.0, display_size[1] / 2.0], Condition::Appearing)
+        .position_pivot([0.5, 0.5])
+        .always_auto_resize(true)
+        .collapsible(false)
+        .build(ui, || {
+            let sender = io.sender.as_ref().unwrap();
+
+            ui.text("ImSpotify needs your permission to:");
+
+            for scope in &requested {
+                ui.bullet_text(&ImString::new(format!("{} ({})", scopes::describe(scope), scope)));
+            }
+
+            ui.text("You'll be asked to log in again to grant it.");
+            ui.separator();
+
+            if ui.button(im_str!("Authorize"), [0.0, 0.0]) {
+                app.request_scopes(requested.clone(), sender.clone());
+            }
+
+            ui.same_line(0.0);
+
+            if ui.button(im_str!("Not now"), [0.0, 0.0]) {
+                app.spotify.state.blocking_lock().scope_request = None;
+
+                // Drop the actions waiting for the scopes
+                let io_state = &mut io.state.blocking_lock();
+                if !io_state.suspended {
+                    io_state.failed.clear();
+                }
+            }
+        });
+}
+
+// Dims widgets for actions the user hasn't granted the scopes for yet
+fn dim_unless_granted(ui: &Ui, granted: &HashSet<String>, required: &[&str]) -> Option<StyleStackToken> {
+    match required.iter().all(|scope| granted.contains(*scope)) {
+        true => None,
+        false => Some(ui.push_style_var(StyleVar::Alpha(0.5)))
+    }
+}
+
+fn draw_login(io: &Io, app: &App, state: &mut UiState, ui: &mut Ui) {
+    if app.spotify.vault().needs_unlock() {
+        return;
+    }
+
+    let (url, error, exchanging) = match &app.spotify.state.blocking_lock().login {
+        LoginState::LoggedIn => return,
+        LoginState::LoggedOut => (None, None, false),
+        LoginState::Authorizing(pending) => {
+            let error = pending.callback_error.lock().unwrap().clone();
+            (Some(pending.url.clone()), error, false)
+        },
+        LoginState::Exchanging => (None, None, true),
+        LoginState::Failed(error) => (None, Some(error.clone()), false),
+        LoginState::Revoked => (None, Some(TokenRevoked.to_string()), false)
+    };
+
+    let display_size = ui.io().display_size;
+
+    Window::new(im_str!("Log in"))
+        .position([display_size[0] / 2.0, display_size[1] / 2.0], Condition::Appearing)
+        .position_pivot([0.5, 0.5])
+        .size([560.0, 0.0], Condition::Appearing)
+        .collapsible(false)
+        .build(ui, || {
+            let sender = io.sender.as_ref().unwrap();
+
+            ui.text(format!("Log in to Spotify with profile '{}'", app.spotify.profile().name));
+            ui.separator();
+
+            if exchanging {
+                ui.text("Logging in...");
+                return;
+            }
+
+            let url = match url {
+                Some(url) => ImString::new(url),
+                None => {
+                    if let Some(error) = &error {
+                        ui.text_colored([1.0, 0.4, 0.4, 1.0], error);
+                    }
+
+                    let label = match error {
+                        Some(_) => im_str!("Log in again"),
+                        None => im_str!("Log in")
+                    };
+
+                    if ui.button(label, [0.0, 0.0]) {
+                        app.start_login(sender.clone());
+                    }
+
+                    return;
+                }
+            };
+
+            match &error {
+                Some(error) => {
+                    ui.text_colored([1.0, 0.4, 0.4, 1.0], error);
+                    ui.text_wrapped(im_str!(
+                        "Authorize ImSpotify in your browser, then paste the URL it redirects to below."
+                    ));
+                },
+                None => ui.text_wrapped(im_str!(
+                    "Authorize ImSpotify in your browser, you'll be logged in once it redirects back."
+                ))
+            }
+
+            ui.input_text(im_str!("URL"), &mut url.clone())
+                .read_only(true)
+                .build();
+
+            if ui.button(im_str!("Open browser"), [0.0, 0.0]) {
+                state.browser_error = webbrowser::open(url.to_str())
+                    .err()
+                    .map(|e| (url.to_str().to_owned(), format!("Unable to open the URL in your browser: {}", e)));
+            }
+
+            ui.same_line(0.0);
+
+            if ui.button(im_str!("Copy URL"), [0.0, 0.0]) {
+                ui.set_clipboard_text(&url);
+            }
+
+            match &state.browser_error {
+                Some((failed, error)) if failed == url.to_str() => {
+                    ui.text_colored([1.0, 0.4, 0.4, 1.0], error);
+                    ui.text_wrapped(im_str!("Copy the URL and open it in your browser yourself."));
+                },
+                _ => ()
+            }
+
+            ui.separator();
+            ui.text_wrapped(im_str!("If the redirect page doesn't load, paste its URL or code here:"));
+
+            let submit = ui.input_text(im_str!("Redirect URL"), &mut state.redirect_url)
+                .enter_returns_true(true)
+                .build();
+
+            ui.same_line(0.0);
+
+            if (ui.button(im_str!("Submit"), [0.0, 0.0]) || submit) && !state.redirect_url.is_empty() {
+                app.submit_redirect_url(state.redirect_url.to_str().to_owned(), sender.clone());
+                state.redirect_url.clear();
+            }
+        });
+}
+
+fn draw_unlock(io: &Io, app: &App, state: &mut UiState, ui: &mut Ui) {
+    let vault = app.spotify.vault();
+    let title = im_str!("Unlock token cache");
+
+    if vault.needs_unlock() {
+        ui.open_popup(title);
+    }
+
+    PopupModal::new(title).always_auto_resize(true).build(ui, || {
+        if !vault.needs_unlock() {
+            ui.close_current_popup();
+            return;
+        }
+
+        let sender = io.sender.as_ref().unwrap();
+        let unlock_error = app.spotify.state.blocking_lock().unlock_error.clone();
+        let exists = vault.exists();
+
+        if exists {
+            ui.text(format!("Enter the passphrase of profile '{}'", app.spotify.profile().name));
+        } else {
+            ui.text("Choose a passphrase to encrypt the token cache");
+        }
+
+        let mut submit = ui.input_text(im_str!("Passphrase"), &mut state.passphrase)
+            .password(true)
+            .enter_returns_true(true)
+            .build();
+
+        if !exists {
+            submit |= ui.input_text(im_str!("Repeat"), &mut state.passphrase_confirm)
+                .password(true)
+                .enter_returns_true(true)
+                .build();
+        }
+
+        let passphrase = state.passphrase.to_str().to_owned();
+        let valid = !passphrase.is_empty()
+            && (exists || passphrase == state.passphrase_confirm.to_str());
+
+        if !exists && !state.passphrase_confirm.to_str().is_empty() && !valid {
+            ui.text_colored([1.0, 0.4, 0.4, 1.0], "The passphrases don't match");
+        }
+
+        if let Some(error) = &unlock_error {
+            ui.text_colored([1.0, 0.4, 0.4, 1.0], error);
+        }
+
+        submit |= ui.button(im_str!("Unlock"), [0.0, 0.0]);
+
+        // Offer a fresh login when the cache can't be decrypted
+        let reset = exists && unlock_error.is_some() && {
+            ui.same_line(0.0);
+            ui.button(im_str!("Log in again with this passphrase"), [0.0, 0.0])
+        };
+
+        if (submit || reset) && valid {
+            app.unlock(Passphrase(passphrase), reset, sender.clone(), io.notifier.clone());
+            state.passphrase.clear();
+            state.passphrase_confirm.clear();
+        }
     });
 }
 
 fn draw_playback(io: &Io, app: &App, ui: &mut Ui) {
     Window::new(im_str!("Playback")).build(ui, || {
-        let app_state = app.spotify.state.blocking_lock();
+        let sender = io.sender.as_ref().unwrap();
+        let mut app_state = app.spotify.state.blocking_lock();
+        let mut scope_request = None;
 
         if let Some(playback) = &app_state.playback {
             let track = playback.item.to_owned().and_then(|i| {
@@ -243,11 +660,57 @@ fn draw_playback(io: &Io, app: &App, ui: &mut Ui) {
                         .elapsed().as_millis()
                 };
 
+                // Until the next fetch notices the track has ended
                 if playback.is_playing {
-                    progress += last_fetch;
+                    progress = (progress + last_fetch).min(duration);
                 }
 
                 ui.text(name);
+
+                let saved = match (&playback.item, &app_state.playback_saved) {
+                    (Some(PlayableItem::Track(t)), Some((id, saved))) if t.id.as_ref() == Some(id) => {
+                        Some((id, *saved))
+                    },
+                    _ => None
+                };
+
+                if let Some((id, saved)) = saved {
+                    ui.same_line(0.0);
+
+                    let (label, color) = if saved {
+                        (im_str!("<3 Liked"), [1.0, 0.4, 0.5, 1.0])
+                    } else {
+                        (im_str!("<3 Like"), [0.7, 0.7, 0.7, 1.0])
+                    };
+
+                    let dimmed = dim_unless_granted(ui, &app_state.scopes, &[scopes::MODIFY_LIBRARY]);
+                    let stack = ui.push_style_color(StyleColor::Text, color);
+                    if ui.small_button(label) {
+                        let event = if saved {
+                            IoEvent::RemoveSavedTrack(id.clone())
+                        } else {
+                            IoEvent::SaveTrack(id.clone())
+                        };
+
+                        sender.send(event).unwrap();
+                    }
+                    stack.pop(ui);
+
+                    if let Some(dimmed) = dimmed {
+                        dimmed.pop(ui);
+                    }
+                } else if matches!(playback.item, Some(PlayableItem::Track(_)))
+                    && !app_state.scopes.contains(scopes::READ_LIBRARY) {
+                    // Whether the track is liked can't be known without reading the library
+                    ui.same_line(0.0);
+
+                    let dimmed = ui.push_style_var(StyleVar::Alpha(0.5));
+                    if ui.small_button(im_str!("<3 Like")) {
+                        scope_request = Some(vec![scopes::READ_LIBRARY, scopes::MODIFY_LIBRARY]);
+                    }
+                    dimmed.pop(ui);
+                }
+
                 ui.text(artists.join(", "));
                 ui.separator();
                 ui.text(format!(
@@ -259,10 +722,194 @@ fn draw_playback(io: &Io, app: &App, ui: &mut Ui) {
                 ProgressBar::new(progress as f32 / duration as f32).build(ui);
             }
         }
+
+        if scope_request.is_some() {
+            app_state.scope_request = scope_request;
+        }
     });
 }
 
-fn format_millis(millis: u128) -> String {
+fn draw_devices(io: &Io, app: &App, ui: &mut Ui) {
+    Window::new(im_str!("Devices")).build(ui, || {
+        let sender = io.sender.as_ref().unwrap();
+        let app_state = app.spotify.state.blocking_lock();
+
+        if ui.button(im_str!("Refresh"), [0.0, 0.0]) {
+            sender.send(IoEvent::FetchDevices).unwrap();
+        }
+
+        ui.separator();
+
+        if let Some(devices) = &app_state.devices {
+            for device in devices {
+                let stack = if device.is_active {
+                    Some(ui.push_style_color(StyleColor::Text, [0.7, 1.0, 1.0, 1.0]))
+                } else {
+                    None
+                };
+
+                let volume = device.volume_percent
+                    .map(|v| format!("{}%", v))
+                    .unwrap_or(String::from("-"));
+
+                ui.text(ImString::new(&device.name));
+
+                let x = ui.cursor_pos()[0];
+                ui.same_line(x + 200.0);
+                ui.text(volume);
+
+                if let Some(stack) = stack {
+                    stack.pop(ui);
+                }
+
+                if let (Some(id), false) = (&device.id, device.is_active) {
+                    ui.same_line(x + 250.0);
+
+                    let label = ImString::new(format!("Transfer##{}", id));
+                    let dimmed = dim_unless_granted(ui, &app_state.scopes, &[scopes::MODIFY_PLAYBACK]);
+
+                    if ui.button(&label, [0.0, 0.0]) {
+                        sender.send(IoEvent::TransferPlayback(id.clone())).unwrap();
+                        sender.send(IoEvent::FetchDevices).unwrap();
+                        sender.send(IoEvent::FetchCurrentPlayback).unwrap();
+                    }
+
+                    if let Some(dimmed) = dimmed {
+                        dimmed.pop(ui);
+                    }
+                }
+            }
+        }
+    });
+}
+
+fn draw_queue(io: &Io, app: &App, ui: &mut Ui) {
+    Window::new(im_str!("Queue")).build(ui, || {
+        let sender = io.sender.as_ref().unwrap();
+        let app_state = app.spotify.state.blocking_lock();
+
+        if ui.button(im_str!("Refresh"), [0.0, 0.0]) {
+            sender.send(IoEvent::FetchQueue).unwrap();
+        }
+
+        ui.separator();
+
+        if let Some(queue) = &app_state.queue {
+            if let Some(item) = &queue.currently_playing {
+                let stack = ui.push_style_color(StyleColor::Text, [0.7, 1.0, 1.0, 1.0]);
+                ui.text(ImString::new(item_title(item)));
+                stack.pop(ui);
+            }
+
+            for item in &queue.queue {
+                ui.text(ImString::new(item_title(item)));
+            }
+        }
+    });
+}
+
+fn draw_log(io: &Io, state: &mut UiState, ui: &mut Ui) {
+    Window::new(im_str!("Log")).build(ui, || {
+        let sender = io.sender.as_ref().unwrap();
+
+        if ui.button(im_str!("Clear"), [0.0, 0.0]) {
+            state.log.clear();
+        }
+
+        ui.separator();
+
+        for (i, entry) in state.log.iter().enumerate().rev() {
+            if draw_notification(i, entry, ui) {
+                sender.send(entry.notification.event.clone().unwrap()).unwrap();
+            }
+
+            ui.separator();
+        }
+    });
+}
+
+// Recent errors, until they time out or get dismissed
+fn draw_toasts(io: &Io, state: &mut UiState, ui: &mut Ui) {
+    let recent = state.log
+        .iter()
+        .filter(|entry| !entry.dismissed && entry.notification.time.elapsed() < TOAST_DURATION)
+        .count();
+
+    if recent == 0 {
+        return;
+    }
+
+    let display_size = ui.io().display_size;
+
+    Window::new(im_str!("##toasts"))
+        .position([display_size[0] - 10.0, display_size[1] - 10.0], Condition::Always)
+        .position_pivot([1.0, 1.0])
+        // A zero height fits the contents
+        .size([400.0, 0.0], Condition::Always)
+        .no_decoration()
+        .focus_on_appearing(false)
+        .bg_alpha(0.85)
+        .build(ui, || {
+            let sender = io.sender.as_ref().unwrap();
+
+            let toasts = state.log
+                .iter_mut()
+                .enumerate()
+                .rev()
+                .filter(|(_, entry)| !entry.dismissed && entry.notification.time.elapsed() < TOAST_DURATION)
+                .take(MAX_TOASTS);
+
+            for (i, entry) in toasts {
+                if draw_notification(i, entry, ui) {
+                    sender.send(entry.notification.event.clone().unwrap()).unwrap();
+                    entry.dismissed = true;
+                }
+
+                ui.same_line(0.0);
+
+                if ui.small_button(&ImString::new(format!("Dismiss##toast{}", i))) {
+                    entry.dismissed = true;
+                }
+            }
+        });
+}
+
+// Returns whether retrying was asked for
+fn draw_notification(id: usize, entry: &LogEntry, ui: &Ui) -> bool {
+    let notification = &entry.notification;
+    let color = match notification.kind {
+        ErrorKind::Auth | ErrorKind::Other => [1.0, 0.4, 0.4, 1.0],
+        ErrorKind::Network | ErrorKind::RateLimit => [1.0, 0.8, 0.4, 1.0],
+        ErrorKind::NoActiveDevice | ErrorKind::PremiumRequired => [0.7, 1.0, 1.0, 1.0],
+        ErrorKind::Info => [0.6, 1.0, 0.6, 1.0]
+    };
+
+    ui.text_colored(color, format!("[{}]", notification.kind));
+    ui.same_line(0.0);
+
+    let repeats = match entry.count {
+        1 => String::new(),
+        count => format!(" (x{})", count)
+    };
+
+    ui.text_disabled(format!("{}s ago{}", notification.time.elapsed().as_secs(), repeats));
+    ui.text_wrapped(&ImString::new(&notification.message));
+
+    notification.event.is_some() && ui.small_button(&ImString::new(format!("Retry##{}", id)))
+}
+
+fn draw_load_progress(progress: &LoadProgress, ui: &Ui) {
+    let overlay = match progress.total {
+        0 => ImString::new("Loading..."),
+        total => ImString::new(format!("Loading {} / {}", progress.loaded, total))
+    };
+
+    ProgressBar::new(progress.fraction())
+        .overlay_text(&overlay)
+        .build(ui);
+}
+
+pub(crate) fn format_millis(millis: u128) -> String {
     let minutes = millis / 60_000;
     let seconds = (millis % 60_000) / 1000;
 
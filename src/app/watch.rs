@@ -0,0 +1,144 @@
+use super::{
+    App,
+    AppResult,
+    status::PlaybackStatus
+};
+use crate::spotify::io::{self, Io, IoEvent, PLAYBACK_POLL_INTERVAL};
+
+use std::{
+    io::Write,
+    time::{Duration, SystemTime, UNIX_EPOCH}
+};
+use serde::Serialize;
+use tokio::time::{Instant, sleep_until};
+
+// Progress drift beyond this is reported as a seek rather than polling jitter
+const SEEK_THRESHOLD_MS: i128 = 3000;
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum WatchEvent {
+    TrackChanged {
+        #[serde(flatten)]
+        status: PlaybackStatus
+    },
+    Paused { progress_ms: u128 },
+    Resumed { progress_ms: u128 },
+    Seeked { from_ms: u128, to_ms: u128 },
+    DeviceChanged { device: String, device_id: Option<String> },
+    VolumeChanged { volume_percent: Option<u32> },
+    Stopped
+}
+
+#[derive(Serialize)]
+struct WatchLine<'a> {
+    timestamp: u128,
+    #[serde(flatten)]
+    event: &'a WatchEvent
+}
+
+impl App {
+    pub(super) async fn watch(&self, interval: Option<Duration>) -> AppResult<()> {
+        let interval = interval.unwrap_or(PLAYBACK_POLL_INTERVAL);
+        let io = Io::default();
+
+        let mut previous: Option<(PlaybackStatus, Instant)> = None;
+        let mut stdout = std::io::stdout();
+
+        loop {
+            let next_fetch = Instant::now() + interval;
+
+            if let Err(e) = io::handle_event(IoEvent::FetchCurrentPlayback, &io, self).await {
+                eprintln!("Error: {:#}", e);
+                sleep_until(next_fetch).await;
+                continue;
+            }
+
+            let current = {
+                let app_state = self.spotify.state.lock().await;
+                app_state.playback.as_ref().and_then(PlaybackStatus::new)
+            };
+
+            let events = diff(
+                previous.as_ref().map(|(status, fetched)| (status, fetched.elapsed())),
+                current.as_ref()
+            );
+
+            for event in events {
+                let line = WatchLine {
+                    timestamp: SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_millis(),
+                    event: &event
+                };
+
+                writeln!(stdout, "{}", serde_json::to_string(&line)?)?;
+            }
+
+            // Downstream readers are usually pipes, which are block buffered
+            stdout.flush()?;
+
+            previous = current.map(|status| (status, Instant::now()));
+            sleep_until(next_fetch).await;
+        }
+    }
+}
+
+pub fn diff(previous: Option<(&PlaybackStatus, Duration)>, current: Option<&PlaybackStatus>) -> Vec<WatchEvent> {
+    let mut events = Vec::new();
+
+    let (prev, elapsed, curr) = match (previous, current) {
+        (None, None) => return events,
+        (Some(_), None) => {
+            events.push(WatchEvent::Stopped);
+            return events;
+        },
+        (None, Some(curr)) => {
+            events.push(WatchEvent::TrackChanged { status: curr.clone() });
+            return events;
+        },
+        (Some((prev, elapsed)), Some(curr)) => (prev, elapsed, curr)
+    };
+
+    if prev.uri != curr.uri || prev.track != curr.track {
+        events.push(WatchEvent::TrackChanged { status: curr.clone() });
+    } else {
+        let played = prev.progress_ms + elapsed.as_millis();
+
+        // When the play state changed in between, it could have happened anytime since the last poll
+        let (earliest, latest) = match (prev.is_playing, curr.is_playing) {
+            (true, true) => (played, played),
+            (false, false) => (prev.progress_ms, prev.progress_ms),
+            _ => (prev.progress_ms, played)
+        };
+
+        let expected = curr.progress_ms.clamp(earliest, latest);
+
+        if (curr.progress_ms as i128 - expected as i128).abs() > SEEK_THRESHOLD_MS {
+            events.push(WatchEvent::Seeked {
+                from_ms: expected.min(prev.duration_ms),
+                to_ms: curr.progress_ms
+            });
+        }
+    }
+
+    match (prev.is_playing, curr.is_playing) {
+        (true, false) => events.push(WatchEvent::Paused { progress_ms: curr.progress_ms }),
+        (false, true) => events.push(WatchEvent::Resumed { progress_ms: curr.progress_ms }),
+        _ => ()
+    }
+
+    if prev.device_id != curr.device_id || prev.device != curr.device {
+        events.push(WatchEvent::DeviceChanged {
+            device: curr.device.clone(),
+            device_id: curr.device_id.clone()
+        });
+    }
+
+    if prev.volume_percent != curr.volume_percent {
+        events.push(WatchEvent::VolumeChanged { volume_percent: curr.volume_percent });
+    }
+
+    events
+}
@@ -1,9 +1,31 @@
-use imspotify::{App, AppResult};
+use imspotify::{
+    App,
+    AppResult,
+    app::command::{take_profile, USAGE}
+};
 
 fn main() -> AppResult<()> {
-    App::builder()
+    let mut args = std::env::args()
+        .skip(1)
+        .collect::<Vec<String>>();
+
+    let profile = match take_profile(&mut args) {
+        Ok(profile) => profile,
+        Err(e) => {
+            eprintln!("Error: {}\n\n{}", e, USAGE);
+            std::process::exit(e.exit_status().code());
+        }
+    };
+
+    let mut builder = App::builder().profile(profile);
+
+    if !args.is_empty() {
+        builder = builder.cli().args(args);
+    }
+
+    let status = builder
         .build()
         .run()?;
 
-    Ok(())
+    std::process::exit(status.code())
 }
@@ -1,127 +1,444 @@
-use crate::AppResult;
-
-use anyhow::Context;
-use std::{
-    net::SocketAddr,
-    sync::Arc
-};
-use tokio::{
-    net::{TcpListener, TcpStream},
-    io::{BufReader, AsyncBufReadExt, AsyncWriteExt}
+use crate::{AppResult, app::paths};
+use super::{
+    callback::{self, CallbackServer, CALLBACK_TIMEOUT},
+    pkce::{self, Verifier},
+    profile::Profile,
+    scheduler::ApiError,
+    scopes,
+    vault::{Vault, VaultError}
 };
+
+use std::{collections::HashSet, fmt, io::Write, sync::Mutex};
+use tokio::sync::Notify;
+use url::Url;
+use qrcode::{QrCode, render::unicode};
+use anyhow::{Context, bail, anyhow};
 use rspotify::{
     prelude::*,
-    scopes,
     AuthCodeSpotify,
     Credentials,
     OAuth, Config,
-    clients::mutex::Mutex,
+    ClientError,
+    http::HttpError
 };
 
-pub async fn oauth_client() -> AppResult<AuthCodeSpotify> {
+// Client configured for the profile, without a token yet
+pub fn build_client(profile: &Profile, vault: &Vault) -> AppResult<AuthCodeSpotify> {
+    let settings = profile.settings()?;
+
+    // With encryption on, plaintext caches are imported into the vault instead
+    if !vault.is_enabled() {
+        profile.migrate_legacy_token_cache()?;
+    }
+
     let oauth = OAuth {
-        redirect_uri: String::from("http://localhost:8888/callback"),
-        scopes: scopes!(
-            "streaming",
-            "playlist-read-collaborative",
-            "playlist-read-private",
-            "playlist-modify-private",
-            "playlist-modify-public",
-            "user-follow-read",
-            "user-follow-modify",
-            "user-library-modify",
-            "user-library-read",
-            "user-modify-playback-state",
-            "user-read-currently-playing",
-            "user-read-playback-state",
-            "user-read-playback-position",
-            "user-read-private",
-            "user-read-recently-played"
-        ),
+        redirect_uri: settings.redirect_uri
+            .clone()
+            .unwrap_or_else(callback::redirect_uri),
+        scopes: scopes::MINIMAL
+            .iter()
+            .map(|s| s.to_string())
+            .collect(),
         ..Default::default()
     };
 
-    let creds = Credentials::from_env()
-        .context("Couldn't load environment variables RSPOTIFY_CLIENT_ID and RSPOTIFY_CLIENT_SECRET")?;
+    // Without a client secret the PKCE flow is used instead
+    let creds = match settings.client_id {
+        Some(id) => Credentials { id, secret: settings.client_secret },
+        None => Credentials::from_env()
+            .or_else(|| std::env::var("RSPOTIFY_CLIENT_ID").ok().map(|id| Credentials::new_pkce(&id)))
+            .context("Couldn't load environment variable RSPOTIFY_CLIENT_ID")?
+    };
 
-    let mut spotify = AuthCodeSpotify::with_config(
+    // An encrypted cache is written by us only, so rspotify must not touch it.
+    // Tokens are refreshed by refresh_if_expired, which notices revoked ones.
+    let spotify = AuthCodeSpotify::with_config(
         creds,
         oauth,
         Config {
-            token_cached: true,
-            token_refreshing: true,
+            token_cached: !vault.is_enabled(),
+            token_refreshing: false,
+            cache_path: profile.token_cache_path()?,
             ..Default::default()
         }
     );
 
-    if let Some(token) = spotify.read_token_cache(false).await.ok().flatten() {
-        spotify.token = Arc::new(Mutex::new(Some(token)));
+    Ok(spotify)
+}
+
+pub async fn oauth_client(profile: &Profile, vault: &Vault) -> AppResult<AuthCodeSpotify> {
+    let spotify = build_client(profile, vault)?;
+    authenticate(&spotify, vault).await?;
+
+    Ok(spotify)
+}
+
+// Loads the cached token, prompting for the passphrase if it's encrypted,
+// or logs in from scratch when there's none
+pub async fn authenticate(spotify: &AuthCodeSpotify, vault: &Vault) -> AppResult<()> {
+    if vault.is_enabled() {
+        unlock_from_terminal(spotify, vault).await?;
+    }
+
+    else if load_cached_token(spotify, vault).await? {
+        return Ok(());
     }
 
-    else {
-        let url = spotify.get_authorize_url(false)?;
-        let code = get_code_from_user(&spotify, url.as_str()).await
-            .context("Couldn't acquire auth code from the user")?;
+    if spotify.token.lock().await.unwrap().is_some() {
+        refresh_if_expired(spotify, vault).await
+    } else {
+        login(spotify, vault).await
+    }
+}
 
-        spotify.request_token(code.as_str()).await?;
-        spotify.write_token_cache().await?;
-    };
+// Reads the plain token cache, returns whether there was a token. Expired
+// tokens are read too, so they can be refreshed instead of logging in again.
+pub async fn load_cached_token(spotify: &AuthCodeSpotify, vault: &Vault) -> AppResult<bool> {
+    match spotify.read_token_cache(true).await.ok().flatten() {
+        Some(token) => {
+            *spotify.token.lock().await.unwrap() = Some(token);
 
-    Ok(spotify)
+            // Only a revoked token is dropped, other failures like being
+            // offline are retried before the next request
+            match refresh_if_expired(spotify, vault).await {
+                Err(e) if is_revoked(&e) => {
+                    *spotify.token.lock().await.unwrap() = None;
+                    Err(e)
+                },
+                _ => Ok(true)
+            }
+        },
+        None => Ok(false)
+    }
 }
 
-async fn get_code_from_user(spotify: &AuthCodeSpotify, url: &str) -> AppResult<String> {
-    match webbrowser::open(url) {
-        Ok(_) => println!("Please proceed to log-in in your browser."),
-        Err(_) => eprintln!(
-            "Unable to open the URL in your browser. \
-            Please navigate here manually: {}", url
-        ),
+pub async fn login(spotify: &AuthCodeSpotify, vault: &Vault) -> AppResult<()> {
+    let pending = begin_login(spotify)?;
+    let code = get_code_from_user(spotify, &pending.url).await
+        .context("Couldn't acquire auth code from the user")?;
+
+    finish_login(spotify, vault, &pending, &code).await
+}
+
+// Logs in without a browser or callback server, for machines accessed remotely
+pub async fn login_headless(spotify: &AuthCodeSpotify, vault: &Vault, qr: bool) -> AppResult<()> {
+    let pending = begin_login(spotify)?;
+
+    println!("Open this URL on any device and log in:\n\n{}\n", pending.url);
+
+    if qr {
+        println!("{}", qr_code(&pending.url)?);
     }
 
-    let addr = "127.0.0.1:8888".parse::<SocketAddr>()?;
-    match TcpListener::bind(&addr).await {
-        Ok(listener) => {
-            let (mut stream, _) = listener.accept().await?;
-            let (reader, _) = stream.split();
+    println!(
+        "You'll be redirected to {}, which may fail to load. \
+        Copy the URL from the address bar and paste it here, or just its code:",
+        spotify.oauth.redirect_uri
+    );
+    std::io::stdout().flush()?;
 
-            let mut buf = String::new();
-            let mut buf_reader = BufReader::new(reader);
-            buf_reader.read_line(&mut buf).await?;
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input)?;
 
-            let header = buf
-                .split_whitespace()
-                .collect::<Vec<&str>>();
+    let code = parse_code_input(spotify, &input)?;
+    finish_login(spotify, vault, &pending, &code).await
+}
 
-            let code = spotify
-                .parse_response_code(format!("{}{}", "http://localhost:8888", header[1]).as_str())
-                .context("Unable to parse the response code")?;
+// Accepts the URL the user was redirected to, or the bare authorization code
+pub fn parse_code_input(spotify: &AuthCodeSpotify, input: &str) -> AppResult<String> {
+    let input = input.trim();
 
-            respond_with_success(&mut stream).await?;
+    if let Ok(url) = Url::parse(input) {
+        let param = |name: &str| url
+            .query_pairs()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.into_owned());
 
-            Ok(code)
-        },
+        if let Some(error) = param("error") {
+            bail!("Authorization was denied: {}", error);
+        }
 
-        Err(_) => {
-            println!("Please enter the URL you were redirected to: ");
-            let mut input = String::new();
-            std::io::stdin().read_line(&mut input)?;
+        if param("state").as_deref() != Some(spotify.oauth.state.as_str()) {
+            bail!("The state parameter doesn't match, please try logging in again");
+        }
+
+        return param("code").context("The URL doesn't contain an authorization code");
+    }
+
+    if input.is_empty() || !input.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        bail!("Expected the redirect URL or an authorization code");
+    }
+
+    Ok(input.to_owned())
+}
+
+fn qr_code(data: &str) -> AppResult<String> {
+    let code = QrCode::new(data.as_bytes())
+        .context("Unable to encode the URL as a QR code")?;
+
+    // Light modules are drawn as blocks, which suits dark terminals
+    let rendered = code.render::<unicode::Dense1x2>()
+        .dark_color(unicode::Dense1x2::Light)
+        .light_color(unicode::Dense1x2::Dark)
+        .build();
+
+    Ok(rendered)
+}
+
+pub async fn granted_scopes(spotify: &AuthCodeSpotify) -> HashSet<String> {
+    spotify.token.lock().await.unwrap()
+        .as_ref()
+        .map(|t| t.scopes.clone())
+        .unwrap_or_default()
+}
+
+// Copy of the client which asks for more scopes on its next login, sharing the same token
+pub fn with_scopes<I: IntoIterator<Item = String>>(spotify: &AuthCodeSpotify, scopes: I) -> AuthCodeSpotify {
+    let mut spotify = spotify.clone();
+    spotify.oauth.scopes.extend(scopes);
+    spotify
+}
+
+// Copy of the client asking for the scopes granted before as well, so
+// logging in again doesn't drop them
+pub async fn with_granted_scopes(spotify: &AuthCodeSpotify) -> AuthCodeSpotify {
+    let mut granted = granted_scopes(spotify).await;
+
+    // A revoked token is no longer loaded, but its cache is still there
+    if let Ok(Some(token)) = spotify.read_token_cache(true).await {
+        granted.extend(token.scopes);
+    }
+
+    with_scopes(spotify, granted)
+}
+
+// Login waiting for the user to authorize the app
+pub struct PendingLogin {
+    pub url: String,
+    pub cancelled: Notify,
+    // Set when the redirect can't be caught, so it has to be pasted
+    pub callback_error: Mutex<Option<String>>,
+    verifier: Option<Verifier>
+}
+
+pub fn begin_login(spotify: &AuthCodeSpotify) -> AppResult<PendingLogin> {
+    if spotify.creds.secret.is_none() {
+        let verifier = Verifier::generate();
+        let url = pkce::authorize_url(spotify, &verifier)?;
+
+        return Ok(PendingLogin {
+            url: url.into(),
+            cancelled: Notify::new(),
+            callback_error: Mutex::new(None),
+            verifier: Some(verifier)
+        });
+    }
+
+    Ok(PendingLogin {
+        url: spotify.get_authorize_url(false)?,
+        cancelled: Notify::new(),
+        callback_error: Mutex::new(None),
+        verifier: None
+    })
+}
+
+pub async fn finish_login(spotify: &AuthCodeSpotify, vault: &Vault, pending: &PendingLogin, code: &str) -> AppResult<()> {
+    match &pending.verifier {
+        Some(verifier) => pkce::request_token(spotify, code, verifier).await?,
+        None => spotify.request_token(code).await
+            .context("Unable to exchange the auth code for a token")?
+    }
 
-            let code = spotify
-                .parse_response_code(&input)
-                .context("Unable to parse the response code")?;
+    write_token_cache(spotify, vault).await
+}
+
+// The file is created beforehand so that the token is never readable by other users
+pub async fn write_token_cache(spotify: &AuthCodeSpotify, vault: &Vault) -> AppResult<()> {
+    if vault.is_enabled() {
+        let token = spotify.token.lock().await.unwrap()
+            .clone()
+            .context("There is no token to cache")?;
+
+        return vault.save(&token);
+    }
+
+    paths::create_private_file(&spotify.config.cache_path)?;
+    spotify.write_token_cache().await
+        .context("Unable to write the token cache")?;
+
+    Ok(())
+}
+
+// Deletes the cached tokens of the profile, returns whether there were any
+pub fn logout(profile: &Profile) -> AppResult<bool> {
+    let mut deleted = false;
+
+    let mut paths = profile.plaintext_token_paths()?;
+    paths.push(profile.encrypted_token_path()?);
+
+    for path in paths {
+        if path.exists() {
+            std::fs::remove_file(&path)
+                .with_context(|| format!("Unable to delete {}", path.display()))?;
+            deleted = true;
+        }
+    }
+
+    Ok(deleted)
+}
+
+#[derive(Debug)]
+pub struct TokenRevoked;
+
+impl fmt::Display for TokenRevoked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The Spotify session has expired or been revoked, please log in again")
+    }
+}
 
-            Ok(code)
+impl std::error::Error for TokenRevoked {}
+
+// Whether the error means the token is no longer accepted by Spotify
+pub fn is_revoked(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| cause.is::<TokenRevoked>()) || status_code(e) == Some(401)
+}
+
+pub fn status_code(e: &anyhow::Error) -> Option<u16> {
+    e.chain().find_map(|cause| {
+        if let Some(e) = cause.downcast_ref::<ApiError>() {
+            return Some(e.status);
+        }
+
+        match cause.downcast_ref::<ClientError>() {
+            Some(ClientError::Http(http)) => match http.as_ref() {
+                HttpError::StatusCode(response) => Some(response.status().as_u16()),
+                _ => None
+            },
+            _ => None
+        }
+    })
+}
+
+// Set by Spotify for player commands it refused
+pub fn error_reason(e: &anyhow::Error) -> Option<&str> {
+    e.chain().find_map(|cause| cause.downcast_ref::<ApiError>()?.reason.as_deref())
+}
+
+// Must run before requests, rspotify doesn't refresh tokens by itself
+pub async fn refresh_if_expired(spotify: &AuthCodeSpotify, vault: &Vault) -> AppResult<()> {
+    if vault.needs_unlock() {
+        // The token doesn't outlive the unlock timeout either
+        *spotify.token.lock().await.unwrap() = None;
+        return Err(anyhow!(VaultError::Locked));
+    }
+
+    // Concurrent requests would refresh the same token, and with rotating
+    // refresh tokens all but the first would fail as revoked. Checking for
+    // expiry after the lock skips the refresh another request just did.
+    let _refreshing = vault.refresh_guard().await;
+
+    let expired = spotify.token.lock().await.unwrap()
+        .as_ref()
+        .map_or(false, |t| t.is_expired());
+
+    if expired {
+        let refreshed = match spotify.creds.secret {
+            Some(_) => spotify.refresh_token().await
+                .context("Unable to refresh the token"),
+            None => pkce::refresh_token(spotify).await
+        };
+
+        // The token endpoint answers invalid_grant with a 400
+        if let Err(e) = refreshed {
+            return match status_code(&e) {
+                Some(400 | 401) => Err(anyhow!(TokenRevoked)),
+                _ => Err(e)
+            };
         }
+
+        write_token_cache(spotify, vault).await?;
+    }
+
+    Ok(())
+}
+
+pub async fn unlock_from_terminal(spotify: &AuthCodeSpotify, vault: &Vault) -> AppResult<()> {
+    // A plaintext cache from before encryption was turned on gets imported
+    if !vault.exists() {
+        let unlocked = vault.unlock(&new_passphrase()?).await?;
+        unlocked.notices().for_each(|notice| eprintln!("{}", notice));
+
+        *spotify.token.lock().await.unwrap() = unlocked.token;
+        return Ok(());
+    }
+
+    let passphrase = rpassword::prompt_password("Passphrase for the token cache: ")?;
+
+    match vault.unlock(&passphrase).await {
+        Ok(unlocked) => {
+            unlocked.notices().for_each(|notice| eprintln!("{}", notice));
+
+            *spotify.token.lock().await.unwrap() = unlocked.token;
+            Ok(())
+        },
+
+        Err(e) if matches!(e.downcast_ref::<VaultError>(), Some(VaultError::Undecryptable(_))) => {
+            eprintln!("{}", e);
+            print!("Delete it and log in again? [y/N] ");
+            std::io::stdout().flush()?;
+
+            let mut answer = String::new();
+            std::io::stdin().read_line(&mut answer)?;
+
+            if !answer.trim().eq_ignore_ascii_case("y") {
+                return Err(e);
+            }
+
+            vault.delete()?;
+
+            let unlocked = vault.unlock(&new_passphrase()?).await?;
+            unlocked.notices().for_each(|notice| eprintln!("{}", notice));
+
+            Ok(())
+        },
+
+        Err(e) => Err(e)
+    }
+}
+
+fn new_passphrase() -> AppResult<String> {
+    let passphrase = rpassword::prompt_password("Choose a passphrase for the token cache: ")?;
+
+    if passphrase.is_empty() {
+        bail!("The passphrase can't be empty");
     }
+
+    if rpassword::prompt_password("Repeat the passphrase: ")? != passphrase {
+        bail!("The passphrases don't match");
+    }
+
+    Ok(passphrase)
 }
 
-async fn respond_with_success(stream: &mut TcpStream) -> AppResult<()> {
-    let contents = String::from("<script>window.close();</script>");
-    let response = format!("HTTP/1.1 200 OK\r\n\r\n{}", contents);
+async fn get_code_from_user(spotify: &AuthCodeSpotify, url: &str) -> AppResult<String> {
+    match webbrowser::open(url) {
+        Ok(_) => println!("Please proceed to log-in in your browser."),
+        Err(_) => eprintln!(
+            "Unable to open the URL in your browser. \
+            Please navigate here manually: {}", url
+        ),
+    }
+
+    match CallbackServer::bind(&spotify.oauth.redirect_uri, &spotify.oauth.state).await {
+        Ok(server) => server.wait_for_code(CALLBACK_TIMEOUT).await,
 
-    stream.write(response.as_bytes()).await?;
-    stream.flush().await?;
+        Err(e) => {
+            eprintln!("{:#}", e);
+            println!("Please enter the URL you were redirected to, or its code: ");
+            let mut input = String::new();
+            std::io::stdin().read_line(&mut input)?;
 
-    return Ok(())
+            parse_code_input(spotify, &input)
+        }
+    }
 }
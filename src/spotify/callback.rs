@@ -0,0 +1,339 @@
+use crate::AppResult;
+
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::Duration
+};
+use anyhow::{Context, bail};
+use url::Url;
+use tokio::{
+    net::{TcpListener, TcpStream},
+    io::{AsyncReadExt, AsyncWriteExt},
+    task::JoinSet,
+    time::{Instant, timeout, timeout_at}
+};
+
+pub const DEFAULT_REDIRECT_URI: &str = "http://localhost:8888/callback";
+pub const CALLBACK_TIMEOUT: Duration = Duration::from_secs(300);
+
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_REQUEST_SIZE: usize = 8192;
+
+pub struct CallbackServer {
+    listener: TcpListener,
+    redirect: Arc<Redirect>
+}
+
+// What the redirect has to look like, shared with the connection tasks
+struct Redirect {
+    path: String,
+    state: String
+}
+
+enum Outcome {
+    Code(String),
+    Denied(String),
+    Ignored
+}
+
+// Reads the redirect URI from RSPOTIFY_REDIRECT_URI, falling back to the default one
+pub fn redirect_uri() -> String {
+    std::env::var("RSPOTIFY_REDIRECT_URI")
+        .unwrap_or_else(|_| String::from(DEFAULT_REDIRECT_URI))
+}
+
+impl CallbackServer {
+    pub async fn bind(redirect_uri: &str, state: &str) -> AppResult<Self> {
+        let url = Url::parse(redirect_uri)
+            .with_context(|| format!("Invalid redirect URI '{}'", redirect_uri))?;
+
+        let host = match url.host_str() {
+            Some("localhost") | None => "127.0.0.1",
+            Some(host) => host
+        };
+
+        let port = url.port_or_known_default()
+            .context("The redirect URI has no port")?;
+
+        let listener = TcpListener::bind((host, port)).await
+            .with_context(|| format!("Unable to listen on {}:{}", host, port))?;
+
+        let redirect = Redirect {
+            path: url.path().to_owned(),
+            state: state.to_owned()
+        };
+
+        Ok(Self { listener, redirect: Arc::new(redirect) })
+    }
+
+    pub fn local_addr(&self) -> AppResult<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    // Serves requests until the redirect with a matching state arrives. Each
+    // connection gets its own task, so idle ones like browser preconnects
+    // don't hold up the redirect.
+    pub async fn wait_for_code(&self, wait: Duration) -> AppResult<String> {
+        let deadline = Instant::now() + wait;
+        let mut connections = JoinSet::new();
+
+        loop {
+            let outcome = tokio::select! {
+                accepted = timeout_at(deadline, self.listener.accept()) => {
+                    let (mut stream, _) = match accepted {
+                        Ok(accepted) => accepted?,
+                        Err(_) => bail!("Timed out waiting for the authorization callback")
+                    };
+
+                    let redirect = self.redirect.clone();
+                    connections.spawn(async move { redirect.handle_connection(&mut stream).await });
+                    continue;
+                },
+                Some(Ok(outcome)) = connections.join_next() => outcome
+            };
+
+            match outcome {
+                Outcome::Code(code) => return Ok(code),
+                Outcome::Denied(error) => bail!("Authorization was denied: {}", error),
+                Outcome::Ignored => continue
+            }
+        }
+    }
+}
+
+impl Redirect {
+    async fn handle_connection(&self, stream: &mut TcpStream) -> Outcome {
+        let request = match timeout(READ_TIMEOUT, read_request(stream)).await {
+            Ok(Ok(request)) => request,
+            _ => return Outcome::Ignored
+        };
+
+        let (outcome, status, page) = self.route(&request);
+        let _ = respond(stream, status, &page).await;
+
+        outcome
+    }
+
+    fn route(&self, request: &str) -> (Outcome, &'static str, String) {
+        let bad_request = |message: &str| (Outcome::Ignored, "400 Bad Request", error_page(message));
+
+        let request_line = request.lines().next().unwrap_or_default();
+        let (method, target) = match request_line.split_whitespace().collect::<Vec<&str>>()[..] {
+            [method, target, version] if version.starts_with("HTTP/") => (method, target),
+            _ => return bad_request("Malformed request")
+        };
+
+        if method != "GET" {
+            return (Outcome::Ignored, "405 Method Not Allowed", error_page("Method not allowed"));
+        }
+
+        let url = match Url::parse("http://localhost").and_then(|base| base.join(target)) {
+            Ok(url) => url,
+            Err(_) => return bad_request("Malformed request target")
+        };
+
+        if url.path() != self.path {
+            return (Outcome::Ignored, "404 Not Found", error_page("Not found"));
+        }
+
+        let param = |name: &str| url
+            .query_pairs()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.into_owned());
+
+        if param("state").as_deref() != Some(self.state.as_str()) {
+            return bad_request("The state parameter doesn't match, please try logging in again");
+        }
+
+        if let Some(error) = param("error") {
+            let page = error_page(&format!("Authorization failed: {}", error));
+            return (Outcome::Denied(error), "200 OK", page);
+        }
+
+        match param("code") {
+            Some(code) => (Outcome::Code(code), "200 OK", success_page()),
+            None => bad_request("The authorization code is missing")
+        }
+    }
+}
+
+async fn read_request(stream: &mut TcpStream) -> AppResult<String> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut chunk).await?;
+
+        if n == 0 {
+            break;
+        }
+
+        buf.extend_from_slice(&chunk[..n]);
+
+        if buf.len() > MAX_REQUEST_SIZE {
+            bail!("Request is too large");
+        }
+    }
+
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+async fn respond(stream: &mut TcpStream, status: &str, body: &str) -> AppResult<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\n\
+        Content-Type: text/html; charset=utf-8\r\n\
+        Content-Length: {}\r\n\
+        Connection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+
+    stream.write_all(response.as_bytes()).await?;
+    stream.flush().await?;
+
+    Ok(())
+}
+
+fn success_page() -> String {
+    page(
+        "Logged in",
+        "You have been logged in to ImSpotify. You can close this window now.",
+        "<script>window.close();</script>"
+    )
+}
+
+fn error_page(message: &str) -> String {
+    page("Login failed", message, "")
+}
+
+fn page(title: &str, message: &str, extra: &str) -> String {
+    format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{0}</title></head>\
+        <body><h1>{0}</h1><p>{1}</p>{2}</body></html>",
+        escape_html(title),
+        escape_html(message),
+        extra
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATE: &str = "abc123";
+
+    async fn server() -> (CallbackServer, SocketAddr) {
+        let server = CallbackServer::bind("http://127.0.0.1:0/callback", STATE).await.unwrap();
+        let addr = server.local_addr().unwrap();
+
+        (server, addr)
+    }
+
+    async fn send(addr: SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        response
+    }
+
+    fn get(target: &str) -> String {
+        format!("GET {} HTTP/1.1\r\nHost: localhost\r\nAccept: text/html\r\n\r\n", target)
+    }
+
+    #[tokio::test]
+    async fn accepts_code_with_matching_state() {
+        let (server, addr) = server().await;
+        let waiter = tokio::spawn(async move { server.wait_for_code(Duration::from_secs(5)).await });
+
+        let response = send(addr, &get("/callback?code=xyz&state=abc123")).await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("Logged in"));
+
+        assert_eq!(waiter.await.unwrap().unwrap(), "xyz");
+    }
+
+    #[tokio::test]
+    async fn ignores_unrelated_and_malformed_requests() {
+        let (server, addr) = server().await;
+        let waiter = tokio::spawn(async move { server.wait_for_code(Duration::from_secs(5)).await });
+
+        let response = send(addr, &get("/favicon.ico")).await;
+        assert!(response.starts_with("HTTP/1.1 404"));
+
+        let response = send(addr, "garbage\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 400"));
+
+        let response = send(addr, "POST /callback?code=xyz&state=abc123 HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 405"));
+
+        let response = send(addr, &get("/callback?state=abc123")).await;
+        assert!(response.starts_with("HTTP/1.1 400"));
+
+        let response = send(addr, &get("/callback?code=xyz&state=abc123")).await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+
+        assert_eq!(waiter.await.unwrap().unwrap(), "xyz");
+    }
+
+    #[tokio::test]
+    async fn idle_connections_dont_block_the_redirect() {
+        let (server, addr) = server().await;
+        let waiter = tokio::spawn(async move { server.wait_for_code(Duration::from_secs(2)).await });
+
+        // Like a browser preconnect, which never sends a request
+        let _idle = TcpStream::connect(addr).await.unwrap();
+
+        // Well within the read timeout of the idle connection
+        let request = get("/callback?code=xyz&state=abc123");
+        let response = timeout(Duration::from_secs(1), send(addr, &request)).await
+            .expect("The redirect was held up by the idle connection");
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+
+        assert_eq!(waiter.await.unwrap().unwrap(), "xyz");
+    }
+
+    #[tokio::test]
+    async fn rejects_mismatched_state() {
+        let (server, addr) = server().await;
+        let waiter = tokio::spawn(async move { server.wait_for_code(Duration::from_secs(5)).await });
+
+        let response = send(addr, &get("/callback?code=evil&state=other")).await;
+        assert!(response.starts_with("HTTP/1.1 400"));
+        assert!(response.contains("state parameter"));
+
+        send(addr, &get("/callback?code=good&state=abc123")).await;
+        assert_eq!(waiter.await.unwrap().unwrap(), "good");
+    }
+
+    #[tokio::test]
+    async fn reports_denied_authorization() {
+        let (server, addr) = server().await;
+        let waiter = tokio::spawn(async move { server.wait_for_code(Duration::from_secs(5)).await });
+
+        let response = send(addr, &get("/callback?error=access_denied&state=abc123")).await;
+        assert!(response.contains("access_denied"));
+
+        let error = waiter.await.unwrap().unwrap_err();
+        assert!(error.to_string().contains("access_denied"));
+    }
+
+    #[tokio::test]
+    async fn times_out_without_callback() {
+        let (server, _) = server().await;
+
+        let error = server.wait_for_code(Duration::from_millis(100)).await.unwrap_err();
+        assert!(error.to_string().contains("Timed out"));
+    }
+}
@@ -1,17 +1,34 @@
 use crate::{App, AppResult};
+use super::{
+    SpotifyState, LoginState, LoadProgress,
+    auth::{self, build_client, load_cached_token, refresh_if_expired},
+    profile::{Profile, PollingSettings},
+    notification::Notification,
+    scheduler::{Priority, Reservation},
+    scopes,
+    vault::Vault,
+    uri::SpotifyUri
+};
 
 use std::{
-    sync::Arc,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering}
+    },
     time::Duration
 };
+use anyhow::Context;
 use rspotify::{
     prelude::{
         OAuthClient,
+        Id,
         PlayableId,
         BaseClient
     },
     model::{
         AdditionalType,
+        CurrentPlaybackContext,
+        PlayableItem,
         TrackId,
         SimplifiedPlaylist,
         PlaylistId, PlaylistItem
@@ -20,42 +37,149 @@ use rspotify::{
 use tokio::{
     sync::{
         Mutex,
+        Notify,
         mpsc::{
+            self,
             UnboundedSender,
             UnboundedReceiver
         }
     },
     time::{
         Instant,
-        sleep_until
+        sleep_until,
+        timeout
     }
 };
 
+pub const PLAYBACK_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+// Spotify takes a moment to apply commands and to start the next track
+const COMMAND_SETTLE_DELAY: Duration = Duration::from_millis(500);
+const TRACK_END_MARGIN: Duration = Duration::from_millis(500);
+
+// How long requests still running at shutdown get to finish
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Largest pages the endpoints allow
+const PLAYLISTS_PAGE_LIMIT: u32 = 50;
+const PLAYLIST_ITEMS_PAGE_LIMIT: u32 = 100;
+
+// Tells the IO tasks to stop
+#[derive(Clone, Default)]
+pub struct Shutdown {
+    requested: Arc<AtomicBool>,
+    notify: Arc<Notify>
+}
+
+impl Shutdown {
+    pub fn request(&self) {
+        self.requested.store(true, Ordering::SeqCst);
+        self.notify.notify_waiters();
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+
+    // Resolves once shutting down has been requested
+    pub async fn requested(&self) {
+        loop {
+            let notified = self.notify.notified();
+
+            if self.is_requested() {
+                return;
+            }
+
+            notified.await;
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct Io {
     pub state: Arc<Mutex<IoState>>,
     pub sender: Option<UnboundedSender<IoEvent>>,
-    pub receiver: Option<UnboundedReceiver<IoEvent>>
+    pub receiver: Option<UnboundedReceiver<IoEvent>>,
+    // Errors go to the UI when there is one
+    pub notifier: Option<UnboundedSender<Notification>>,
+    pub shutdown: Shutdown
 }
 
 #[derive(Default)]
 pub struct IoState {
-    pub playback_last_fetch: Option<Instant>
+    pub playback_last_fetch: Option<Instant>,
+    // Set when the token got revoked, until the user logs in again
+    pub suspended: bool,
+    pub failed: Vec<IoEvent>,
+    pub polling: PollingSettings,
+    pub unfocused: bool,
+    // Set after commands that change the playback
+    pub refresh_at: Option<Instant>,
+    pub polls_without_device: u32,
+    // Item whose end was polled for already, a progress stuck past the end
+    // would otherwise keep polling
+    pub track_end_polled: Option<String>,
+    // Wakes the playback task up to reschedule the next fetch
+    pub playback_refresh: Arc<Notify>
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum IoEvent {
     FetchUserInfo,
     FetchCurrentPlayback,
     FetchPlaylists,
-    FetchPlaylistItems(PlaylistId),
-    PushPlayback(TrackId)
+    // Tagged with the generation of the selection it was sent for
+    FetchPlaylistItems(PlaylistId, u64),
+    FetchDevices,
+    FetchQueue,
+    PushPlayback(TrackId),
+    TransferPlayback(String),
+    AddToQueue(SpotifyUri),
+    SaveTrack(TrackId),
+    RemoveSavedTrack(TrackId),
+    SwitchProfile(Profile),
+    Logout,
+    Resume
+}
+
+impl IoEvent {
+    // Session events must work while logged out or locked
+    fn is_session_event(&self) -> bool {
+        matches!(self, Self::SwitchProfile(_) | Self::Logout | Self::Resume)
+    }
+
+    // Actions started by the user run next to the background fetches
+    pub fn priority(&self) -> Priority {
+        match self {
+            Self::PushPlayback(_)
+            | Self::TransferPlayback(_)
+            | Self::AddToQueue(_)
+            | Self::SaveTrack(_)
+            | Self::RemoveSavedTrack(_) => Priority::Interactive,
+            _ => Priority::Background
+        }
+    }
+
+    pub fn required_scopes(&self) -> &'static [&'static str] {
+        match self {
+            Self::FetchCurrentPlayback | Self::FetchDevices => &[scopes::READ_PLAYBACK],
+            Self::FetchQueue => &[scopes::READ_PLAYBACK, scopes::READ_CURRENTLY_PLAYING],
+            Self::FetchPlaylists | Self::FetchPlaylistItems(..) => &[scopes::READ_PLAYLISTS],
+            Self::PushPlayback(_) | Self::TransferPlayback(_) | Self::AddToQueue(_) => &[scopes::MODIFY_PLAYBACK],
+            Self::SaveTrack(_) | Self::RemoveSavedTrack(_) => &[scopes::MODIFY_LIBRARY],
+            Self::FetchUserInfo | Self::SwitchProfile(_) | Self::Logout | Self::Resume => &[]
+        }
+    }
 }
 
 impl Clone for Io {
     fn clone(&self) -> Self {
         Io {
             state: self.state.clone(),
+            sender: self.sender.clone(),
+            notifier: self.notifier.clone(),
+            shutdown: self.shutdown.clone(),
             ..Default::default()
         }
     }
@@ -65,85 +189,616 @@ pub async fn main_loop(mut io: Io, app: App) {
     let mut receiver = io.receiver
         .take().unwrap();
 
-    let playback_task = {
+    let mut playback_task = {
         let io = io.clone();
         let app = app.clone();
 
         tokio::spawn(async move { loop {
-            let next_fetch = {
+            let playback = app.spotify.state.lock().await.playback.clone();
+
+            let (next_fetch, track_end, refresh) = {
                 let io_state = io.state.lock().await;
-                match io_state.playback_last_fetch {
-                    Some(last_fetch) => last_fetch + Duration::from_secs(5),
-                    None => Instant::now() + Duration::from_secs(5)
-                }
+                let (next_fetch, track_end) = next_poll(&io_state, playback.as_ref());
+                (next_fetch, track_end, io_state.playback_refresh.clone())
             };
 
-            sleep_until(next_fetch).await;
+            tokio::select! {
+                _ = sleep_until(next_fetch) => (),
+                // Something changed, schedule again
+                _ = refresh.notified() => continue,
+                _ = io.shutdown.requested() => break
+            }
+
+            {
+                let io_state = &mut io.state.lock().await;
+                io_state.refresh_at = None;
+
+                if track_end.is_some() {
+                    io_state.track_end_polled = track_end;
+                }
+            }
+
+            let logged_in = matches!(app.spotify.state.lock().await.login, LoginState::LoggedIn);
 
-            match handle_event(IoEvent::FetchCurrentPlayback, &io, &app).await {
+            if !logged_in || app.spotify.vault().needs_unlock() {
+                io.state.lock().await.playback_last_fetch = Some(Instant::now());
+                continue;
+            }
+
+            let result = tokio::select! {
+                result = handle_event(IoEvent::FetchCurrentPlayback, &io, &app) => result,
+                // Dropping the request cancels it
+                _ = io.shutdown.requested() => break
+            };
+
+            let e = match result {
                 Ok(_) => continue,
-                Err(e) => eprintln!("Error in IO thread: {}", e),
+                Err(e) => e
+            };
+
+            // A failed poll waits a full interval too, instead of retrying right away
+            io.state.lock().await.playback_last_fetch = Some(Instant::now());
+
+            match e {
+                e if auth::is_revoked(&e) => suspend(&io, &app).await,
+                // Polling doesn't ask for scopes by itself
+                e if scopes::find_missing(&e).is_some() => (),
+                e => report(&io, &e, Some(IoEvent::FetchCurrentPlayback)),
             };
         }})
     };
 
-    while let Some(event) = receiver.recv().await {
-        match handle_event(event, &io, &app).await {
-            Ok(_) => continue,
-            Err(e) => eprintln!("Error in IO thread: {}", e),
+    // Each lane handles its events in order, so a long fetch doesn't hold up playback control
+    let (interactive, mut interactive_task) = {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<(IoEvent, Reservation)>();
+        let io = io.clone();
+        let app = app.clone();
+
+        (sender, tokio::spawn(async move {
+            while let Some((event, _reservation)) = next_event(&mut receiver, &io.shutdown).await {
+                dispatch(event, &io, &app).await;
+            }
+        }))
+    };
+
+    let (background, mut background_task) = {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<IoEvent>();
+        let io = io.clone();
+        let app = app.clone();
+
+        (sender, tokio::spawn(async move {
+            while let Some(event) = next_event(&mut receiver, &io.shutdown).await {
+                dispatch(event, &io, &app).await;
+            }
+        }))
+    };
+
+    // The UI holds a sender until the end, so the channel doesn't close by itself
+    while let Some(event) = next_event(&mut receiver, &io.shutdown).await {
+        let events = match event {
+            IoEvent::Resume => {
+                let io_state = &mut io.state.lock().await;
+                io_state.suspended = false;
+                std::mem::take(&mut io_state.failed)
+            },
+            event => vec![event]
         };
+
+        for event in events {
+            let sent = match event.priority() {
+                Priority::Interactive => {
+                    let reservation = app.spotify.scheduler.reserve();
+                    interactive.send((event, reservation)).is_ok()
+                },
+                Priority::Background => background.send(event).is_ok()
+            };
+
+            if !sent {
+                eprintln!("Error in IO thread: an event lane has stopped");
+            }
+        }
+    }
+
+    drop(interactive);
+    drop(background);
+
+    // Events in flight get a moment to finish, then they're cancelled
+    let drained = timeout(DRAIN_TIMEOUT, async {
+        tokio::join!(&mut interactive_task, &mut background_task, &mut playback_task)
+    }).await;
+
+    match drained {
+        Ok((interactive, background, playback)) => {
+            interactive.unwrap();
+            background.unwrap();
+            playback.unwrap();
+        },
+        Err(_) => {
+            eprintln!("Cancelling requests still running at shutdown");
+
+            for task in [interactive_task, background_task, playback_task] {
+                task.abort();
+            }
+        }
     }
 
-    playback_task.await.unwrap();
+    flush(&app).await;
+}
+
+// Stops handing out events once shutting down, queued ones are dropped
+async fn next_event<T>(receiver: &mut UnboundedReceiver<T>, shutdown: &Shutdown) -> Option<T> {
+    tokio::select! {
+        biased;
+        _ = shutdown.requested() => None,
+        event = receiver.recv() => event
+    }
+}
+
+// Saves what only lives in memory before the runtime goes away
+async fn flush(app: &App) {
+    app.spotify.state.lock().await.login.cancel();
+
+    let client = app.spotify.client();
+    let vault = app.spotify.vault();
+    let logged_in = client.token.lock().await.unwrap().is_some();
+
+    if logged_in && !vault.needs_unlock() {
+        if let Err(e) = auth::write_token_cache(&client, &vault).await {
+            eprintln!("Error in IO thread: {:#}", e);
+        }
+    }
+}
+
+// Events sent while logged out, or failing because of a revoked token, are
+// replayed after logging in
+async fn dispatch(event: IoEvent, io: &Io, app: &App) {
+    let logged_in = matches!(app.spotify.state.lock().await.login, LoginState::LoggedIn);
+
+    if !event.is_session_event() && (!logged_in || io.state.lock().await.suspended) {
+        io.state.lock().await.failed.push(event);
+        return;
+    }
+
+    match handle_event(event.clone(), io, app).await {
+        Ok(_) => (),
+        Err(e) if auth::is_revoked(&e) => {
+            suspend(io, app).await;
+            io.state.lock().await.failed.push(event);
+        },
+        // Asks the UI to authorize the scopes, the event is replayed afterwards
+        Err(e) if scopes::find_missing(&e).is_some() => {
+            app.spotify.state.lock().await.scope_request = scopes::find_missing(&e);
+            io.state.lock().await.failed.push(event);
+        },
+        Err(e) => report(io, &e, Some(event))
+    }
+}
+
+fn report(io: &Io, e: &anyhow::Error, event: Option<IoEvent>) {
+    let notification = Notification::new(e, event);
+
+    let sent = match &io.notifier {
+        Some(notifier) => notifier.send(notification).is_ok(),
+        None => false
+    };
+
+    if !sent {
+        eprintln!("Error in IO thread: {}", e);
+    }
+}
+
+// Stops polling and asks the UI to log in again
+async fn suspend(io: &Io, app: &App) {
+    io.state.lock().await.suspended = true;
+
+    // Ask for the same scopes again when logging back in
+    let client = app.spotify.client();
+    let granted = auth::granted_scopes(&client).await;
+    app.spotify.set_client(auth::with_scopes(&client, granted));
+
+    *client.token.lock().await.unwrap() = None;
+
+    let state = &mut app.spotify.state.lock().await;
+    state.login.cancel();
+    state.login = LoginState::Revoked;
 }
 
 pub async fn handle_event(event: IoEvent, io: &Io, app: &App) -> AppResult<()> {
-    let client = &app.spotify.client;
+    let client = &app.spotify.client();
+    let vault = app.spotify.vault();
+
+    if !event.is_session_event() {
+        refresh_if_expired(client, &vault).await?;
+    }
+
+    let granted = auth::granted_scopes(client).await;
+    scopes::check(&granted, event.required_scopes())?;
+
+    let scheduler = &app.spotify.scheduler;
+    let priority = event.priority();
 
     match event {
         IoEvent::FetchUserInfo => {
-            let me = client.me().await?;
+            let me = scheduler.call(priority, || client.me()).await?;
             let app_state = &mut app
                 .spotify.state
                 .lock().await;
 
             app_state.me = Some(me);
+            app_state.scopes = granted;
         },
 
         IoEvent::FetchCurrentPlayback => {
-            let playback = client.current_playback(
+            let playback = scheduler.call(priority, || client.current_playback(
                 None,
                 Some(vec![&AdditionalType::Episode, &AdditionalType::Track])
-            ).await?;
+            )).await?;
+
+            let track_id = match playback.as_ref().and_then(|p| p.item.as_ref()) {
+                Some(PlayableItem::Track(track)) => track.id.clone(),
+                _ => None
+            };
+
+            // Only check the library when the playing track changes
+            let saved_track = app.spotify.state.lock().await
+                .playback_saved.as_ref()
+                .map(|(id, _)| id.clone());
+
+            let playback_saved = match track_id {
+                Some(id) if saved_track.as_ref() != Some(&id) && granted.contains(scopes::READ_LIBRARY) => {
+                    let saved = scheduler.call(priority, || {
+                        client.current_user_saved_tracks_contains([&id])
+                    }).await?;
+                    Some((id, saved.first().copied().unwrap_or(false)))
+                },
+                _ => None
+            };
 
             let app_state = &mut app.spotify.state.lock().await;
             let io_state = &mut io.state.lock().await;
 
-            app_state.playback = playback;
             io_state.playback_last_fetch = Some(Instant::now());
+            io_state.polls_without_device = match playback {
+                Some(_) => 0,
+                None => io_state.polls_without_device.saturating_add(1)
+            };
+
+            app_state.playback = playback;
+
+            if playback_saved.is_some() {
+                app_state.playback_saved = playback_saved;
+            }
         },
 
         IoEvent::FetchPlaylists => {
-            let stream = client.current_user_playlists_manual(None, None);
-            let playlists: Vec<SimplifiedPlaylist> = stream.await?.items;
+            let mut progress = LoadProgress::default();
+            app.spotify.state.lock().await.playlists_progress = Some(progress);
 
-            let app_state = &mut app.spotify.state.lock().await;
-            app_state.playlists = Some(playlists);
+            // Pages are shown as they arrive
+            loop {
+                let page = scheduler.call(priority, || client.current_user_playlists_manual(
+                    Some(PLAYLISTS_PAGE_LIMIT),
+                    Some(progress.loaded)
+                )).await;
+
+                let page = match page {
+                    Ok(page) => page,
+                    Err(e) => {
+                        app.spotify.state.lock().await.playlists_progress = None;
+                        return Err(e);
+                    }
+                };
+
+                let done = page.next.is_none() || page.items.is_empty();
+                let first = progress.loaded == 0;
+
+                progress.loaded += page.items.len() as u32;
+                progress.total = page.total;
+
+                let app_state = &mut app.spotify.state.lock().await;
+                let items: Vec<SimplifiedPlaylist> = page.items;
+
+                match (&mut app_state.playlists, first) {
+                    (Some(playlists), false) => playlists.extend(items),
+                    _ => app_state.playlists = Some(items)
+                }
+
+                if done {
+                    app_state.playlists_progress = None;
+                    break;
+                }
+
+                app_state.playlists_progress = Some(progress);
+            }
         },
 
-        IoEvent::FetchPlaylistItems(id) => {
-            let stream = client.playlist_items_manual(&id, None, None, None, None);
-            let items: Vec<PlaylistItem> = stream.await?.items;
+        IoEvent::FetchPlaylistItems(id, generation) => {
+            let selection = &app.spotify.playlist_items_generation;
+            let mut progress = LoadProgress::default();
 
-            let app_state = &mut app.spotify.state.lock().await;
-            app_state.selected_playlist_items = Some(items);
+            loop {
+                // Selecting another playlist cancels the request in flight
+                let page = tokio::select! {
+                    page = scheduler.call(priority, || client.playlist_items_manual(
+                        &id,
+                        None,
+                        None,
+                        Some(PLAYLIST_ITEMS_PAGE_LIMIT),
+                        Some(progress.loaded)
+                    )) => page,
+                    _ = selection.superseded(generation) => return Ok(())
+                };
+
+                let app_state = &mut app.spotify.state.lock().await;
+
+                // The selection is changed under the same lock, so this can't race
+                if !selection.is_current(generation) {
+                    return Ok(());
+                }
+
+                let page = match page {
+                    Ok(page) => page,
+                    Err(e) => {
+                        app_state.playlist_items_progress = None;
+                        return Err(e);
+                    }
+                };
+
+                let done = page.next.is_none() || page.items.is_empty();
+                let first = progress.loaded == 0;
+
+                progress.loaded += page.items.len() as u32;
+                progress.total = page.total;
+
+                let items: Vec<PlaylistItem> = page.items;
+
+                match (&mut app_state.selected_playlist_items, first) {
+                    (Some(selected), false) => selected.extend(items),
+                    _ => app_state.selected_playlist_items = Some(items)
+                }
+
+                if done {
+                    app_state.playlist_items_progress = None;
+                    break;
+                }
+
+                app_state.playlist_items_progress = Some(progress);
+            }
         },
 
         IoEvent::PushPlayback(id) => {
             let playable_id: &dyn PlayableId = &id;
-            client.start_uris_playback([playable_id], None, None, None).await?;
-        }
+            scheduler.call(priority, || {
+                client.start_uris_playback([playable_id], None, None, None)
+            }).await?;
+
+            request_refresh(io, COMMAND_SETTLE_DELAY).await;
+        },
+
+        IoEvent::FetchDevices => {
+            let devices = scheduler.call(priority, || client.device()).await?;
+
+            let app_state = &mut app.spotify.state.lock().await;
+            app_state.devices = Some(devices);
+        },
+
+        IoEvent::FetchQueue => {
+            let queue = scheduler.call(priority, || client.current_user_queue()).await?;
+
+            let app_state = &mut app.spotify.state.lock().await;
+            app_state.queue = Some(queue);
+        },
+
+        IoEvent::TransferPlayback(device_id) => {
+            scheduler.call(priority, || client.transfer_playback(&device_id, None)).await?;
+
+            request_refresh(io, COMMAND_SETTLE_DELAY).await;
+        },
+
+        IoEvent::AddToQueue(uri) => {
+            let playable_id = uri.as_playable()
+                .context("Only tracks and episodes can be added to the queue")?;
+
+            scheduler.call(priority, || client.add_item_to_queue(playable_id, None)).await?;
+        },
+
+        IoEvent::SaveTrack(id) => {
+            scheduler.call(priority, || client.current_user_saved_tracks_add([&id])).await?;
+
+            let app_state = &mut app.spotify.state.lock().await;
+            app_state.playback_saved = Some((id, true));
+        },
+
+        IoEvent::RemoveSavedTrack(id) => {
+            scheduler.call(priority, || client.current_user_saved_tracks_delete([&id])).await?;
+
+            let app_state = &mut app.spotify.state.lock().await;
+            app_state.playback_saved = Some((id, false));
+        },
+
+        IoEvent::SwitchProfile(profile) => {
+            let vault = Vault::for_profile(&profile)?;
+            let client = build_client(&profile, &vault)?;
+
+            // Without a usable cached token the login panel takes over, so the
+            // switch goes through even when the token got revoked
+            let login = match vault.is_enabled() {
+                true => LoginState::LoggedOut,
+                false => match load_cached_token(&client, &vault).await {
+                    Ok(true) => LoginState::LoggedIn,
+                    Ok(false) => LoginState::LoggedOut,
+                    Err(e) => LoginState::Failed(format!("Unable to log-in with profile '{}': {:#}", profile.name, e))
+                }
+            };
+
+            let logged_in = matches!(login, LoginState::LoggedIn);
+
+            app.spotify.set_client(client);
+            app.spotify.set_profile(profile);
+            app.spotify.set_vault(vault);
+
+            reset_state(io, app).await;
+            app.spotify.state.lock().await.login = login;
+
+            if logged_in {
+                if let Some(sender) = &io.sender {
+                    fetch_all(sender)?;
+                }
+            }
+        },
+
+        IoEvent::Logout => {
+            auth::logout(&app.spotify.profile())?;
+            vault.lock();
+            *client.token.lock().await.unwrap() = None;
+
+            reset_state(io, app).await;
+        },
+
+        // Replaying failed events is up to the main loop
+        IoEvent::Resume => ()
     };
 
     Ok(())
-}
\ No newline at end of file
+}
+
+pub fn fetch_all(sender: &UnboundedSender<IoEvent>) -> AppResult<()> {
+    for event in [
+        IoEvent::FetchUserInfo,
+        IoEvent::FetchPlaylists,
+        IoEvent::FetchCurrentPlayback,
+        IoEvent::FetchDevices,
+        IoEvent::FetchQueue
+    ] {
+        sender.send(event)?;
+    }
+
+    Ok(())
+}
+
+async fn reset_state(io: &Io, app: &App) {
+    let state = &mut app.spotify.state.lock().await;
+    state.login.cancel();
+    **state = SpotifyState::default();
+
+    // Playlist items still loading belong to the previous session
+    app.spotify.playlist_items_generation.next();
+
+    // The playback task keeps waiting on the same notify
+    let io_state = &mut io.state.lock().await;
+    **io_state = IoState {
+        polling: polling_settings(&app.spotify.profile()),
+        unfocused: io_state.unfocused,
+        playback_refresh: io_state.playback_refresh.clone(),
+        ..Default::default()
+    };
+}
+
+fn polling_settings(profile: &Profile) -> PollingSettings {
+    profile.settings()
+        .map(|settings| settings.polling)
+        .unwrap_or_default()
+}
+
+// Polls faster while playing, and right when the track should end. Returns
+// the item too when the poll is for its end.
+fn next_poll(io_state: &IoState, playback: Option<&CurrentPlaybackContext>) -> (Instant, Option<String>) {
+    let polling = &io_state.polling;
+    let last_fetch = io_state.playback_last_fetch.unwrap_or_else(Instant::now);
+
+    let interval = match playback {
+        None => {
+            let backoff = 2u64.saturating_pow(io_state.polls_without_device.saturating_sub(1).min(16));
+            Duration::from_secs(polling.no_device.saturating_mul(backoff))
+                .min(Duration::from_secs(polling.no_device_max))
+        },
+        Some(playback) if playback.is_playing => Duration::from_secs(polling.playing),
+        Some(_) => Duration::from_secs(polling.paused)
+    };
+
+    let interval = match io_state.unfocused {
+        true => interval.max(Duration::from_secs(polling.unfocused)),
+        false => interval
+    };
+
+    let next = match io_state.refresh_at {
+        Some(refresh_at) => (last_fetch + interval).min(refresh_at),
+        None => last_fetch + interval
+    };
+
+    let track_end = playback
+        .filter(|playback| playback.is_playing)
+        .and_then(|playback| Some((playback.progress?, playback.item.as_ref()?)))
+        .map(|(progress, item)| {
+            let remaining = item_duration(item).saturating_sub(progress) + TRACK_END_MARGIN;
+            (last_fetch + remaining, item_key(item))
+        })
+        .filter(|(_, key)| io_state.track_end_polled.as_ref() != Some(key));
+
+    match track_end {
+        Some((track_end, key)) if track_end < next => (track_end, Some(key)),
+        _ => (next, None)
+    }
+}
+
+// Local tracks have no ID
+fn item_key(item: &PlayableItem) -> String {
+    match item {
+        PlayableItem::Track(track) => track.id
+            .as_ref()
+            .map_or_else(|| track.name.clone(), |id| id.uri()),
+        PlayableItem::Episode(episode) => episode.id.uri()
+    }
+}
+
+fn item_duration(item: &PlayableItem) -> Duration {
+    match item {
+        PlayableItem::Track(track) => track.duration,
+        PlayableItem::Episode(episode) => episode.duration
+    }
+}
+
+// Fetches the playback again once the command has taken effect
+async fn request_refresh(io: &Io, delay: Duration) {
+    let io_state = &mut io.state.lock().await;
+    let refresh_at = Instant::now() + delay;
+
+    io_state.refresh_at = Some(io_state.refresh_at.map_or(refresh_at, |at| at.min(refresh_at)));
+    io_state.playback_refresh.notify_one();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shutdown_finishes_within_timeout() {
+        let app = App::builder().build();
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        // Holding on to a sender like the UI does keeps the channel open
+        let io = Io {
+            sender: Some(sender),
+            receiver: Some(receiver),
+            ..Default::default()
+        };
+
+        let shutdown = io.shutdown.clone();
+
+        app.rt.block_on(async {
+            let handle = tokio::spawn(main_loop(io, app.clone()));
+
+            // Let the playback task start waiting for its next poll
+            tokio::time::sleep(Duration::from_millis(100)).await;
+
+            let started = Instant::now();
+            shutdown.request();
+
+            timeout(SHUTDOWN_TIMEOUT, handle).await
+                .expect("Shutting down took too long")
+                .unwrap();
+
+            assert!(started.elapsed() < DRAIN_TIMEOUT);
+        });
+    }
+}
@@ -1,21 +1,66 @@
 pub mod auth;
+pub mod callback;
 pub mod io;
+pub mod notification;
+pub mod pkce;
+pub mod profile;
+pub mod scheduler;
+pub mod scopes;
+pub mod search;
+pub mod uri;
+pub mod vault;
 
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use self::{auth::PendingLogin, profile::Profile, scheduler::Scheduler, vault::Vault};
+
+use std::{
+    collections::HashSet,
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicU64, Ordering}
+    }
+};
+use tokio::sync::{Mutex, Notify};
 use rspotify::{
     AuthCodeSpotify,
     model::{
         PrivateUser,
         CurrentPlaybackContext,
-        SimplifiedPlaylist, PlaylistItem
+        CurrentUserQueue,
+        Device,
+        PlayableItem,
+        SimplifiedPlaylist, PlaylistItem,
+        TrackId
     }
 };
 
 #[derive(Clone)]
 pub struct Spotify {
-    pub client: AuthCodeSpotify,
-    pub state: Arc<Mutex<SpotifyState>>
+    client: Arc<RwLock<AuthCodeSpotify>>,
+    profile: Arc<RwLock<Profile>>,
+    vault: Arc<RwLock<Vault>>,
+    pub state: Arc<Mutex<SpotifyState>>,
+    pub scheduler: Scheduler,
+    pub playlist_items_generation: Generation
+}
+
+#[derive(Default)]
+pub enum LoginState {
+    #[default]
+    LoggedOut,
+    Authorizing(Arc<PendingLogin>),
+    Exchanging,
+    Failed(String),
+    Revoked,
+    LoggedIn
+}
+
+impl LoginState {
+    // Stops the callback server of a pending login
+    pub fn cancel(&self) {
+        if let Self::Authorizing(pending) = self {
+            pending.cancelled.notify_one();
+        }
+    }
 }
 
 #[derive(Default)]
@@ -24,14 +69,117 @@ pub struct SpotifyState {
     pub playback: Option<CurrentPlaybackContext>,
     pub playlists: Option<Vec<SimplifiedPlaylist>>,
     pub selected_playlist: Option<SimplifiedPlaylist>,
-    pub selected_playlist_items: Option<Vec<PlaylistItem>>
+    pub selected_playlist_items: Option<Vec<PlaylistItem>>,
+    // Set while paging through the collections
+    pub playlists_progress: Option<LoadProgress>,
+    pub playlist_items_progress: Option<LoadProgress>,
+    pub devices: Option<Vec<Device>>,
+    pub queue: Option<CurrentUserQueue>,
+    pub playback_saved: Option<(TrackId, bool)>,
+    pub unlock_error: Option<String>,
+    pub login: LoginState,
+    pub scopes: HashSet<String>,
+    // Scopes to ask the user for, after an action needed them
+    pub scope_request: Option<Vec<&'static str>>
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct LoadProgress {
+    pub loaded: u32,
+    pub total: u32
+}
+
+impl LoadProgress {
+    pub fn fraction(&self) -> f32 {
+        match self.total {
+            0 => 0.0,
+            total => self.loaded as f32 / total as f32
+        }
+    }
+}
+
+// Bumped whenever a request replaces the previous one, so the results of the
+// superseded request can be recognized and dropped
+#[derive(Clone, Default)]
+pub struct Generation {
+    current: Arc<AtomicU64>,
+    changed: Arc<Notify>
+}
+
+impl Generation {
+    pub fn next(&self) -> u64 {
+        let generation = self.current.fetch_add(1, Ordering::SeqCst) + 1;
+        self.changed.notify_waiters();
+        generation
+    }
+
+    pub fn is_current(&self, generation: u64) -> bool {
+        self.current.load(Ordering::SeqCst) == generation
+    }
+
+    // Resolves once a newer generation has been started
+    pub async fn superseded(&self, generation: u64) {
+        loop {
+            let changed = self.changed.notified();
+
+            if !self.is_current(generation) {
+                return;
+            }
+
+            changed.await;
+        }
+    }
 }
 
 impl Default for Spotify {
     fn default() -> Self {
         Self {
             client: Default::default(),
-            state: Arc::new(Mutex::new(Default::default()))
+            profile: Default::default(),
+            vault: Default::default(),
+            state: Arc::new(Mutex::new(Default::default())),
+            scheduler: Default::default(),
+            playlist_items_generation: Default::default()
         }
     }
 }
+
+impl Spotify {
+    pub fn client(&self) -> AuthCodeSpotify {
+        self.client.read().unwrap().clone()
+    }
+
+    pub fn set_client(&self, client: AuthCodeSpotify) {
+        *self.client.write().unwrap() = client;
+    }
+
+    pub fn profile(&self) -> Profile {
+        self.profile.read().unwrap().clone()
+    }
+
+    pub fn set_profile(&self, profile: Profile) {
+        *self.profile.write().unwrap() = profile;
+    }
+
+    pub fn vault(&self) -> Vault {
+        self.vault.read().unwrap().clone()
+    }
+
+    pub fn set_vault(&self, vault: Vault) {
+        *self.vault.write().unwrap() = vault;
+    }
+}
+
+pub fn item_title(item: &PlayableItem) -> String {
+    match item {
+        PlayableItem::Track(t) => format!(
+            "{} - {}",
+            t.artists.iter()
+                .map(|a| a.name.clone())
+                .collect::<Vec<String>>()
+                .join(", "),
+            t.name
+        ),
+        PlayableItem::Episode(e) => format!("{} - {}", e.show.name, e.name)
+    }
+}
@@ -0,0 +1,104 @@
+use super::{
+    auth::{self, TokenRevoked},
+    io::IoEvent,
+    vault::VaultError
+};
+
+use std::fmt;
+use rspotify::{ClientError, http::HttpError};
+use tokio::time::Instant;
+
+// Errors of the IO thread, reported to the UI
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub kind: ErrorKind,
+    pub message: String,
+    // Sent again when retrying
+    pub event: Option<IoEvent>,
+    pub time: Instant
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Auth,
+    Network,
+    RateLimit,
+    NoActiveDevice,
+    PremiumRequired,
+    Other,
+    // Not an error, just something the user should know about
+    Info
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self {
+            Self::Auth => "Auth",
+            Self::Network => "Network",
+            Self::RateLimit => "Rate limit",
+            Self::NoActiveDevice => "No active device",
+            Self::PremiumRequired => "Premium required",
+            Self::Other => "Error",
+            Self::Info => "Info"
+        };
+
+        write!(f, "{}", label)
+    }
+}
+
+impl Notification {
+    pub fn new(e: &anyhow::Error, event: Option<IoEvent>) -> Self {
+        let kind = ErrorKind::classify(e, event.as_ref());
+
+        let message = match kind {
+            ErrorKind::NoActiveDevice => String::from("No active device, start playing on one of your devices first"),
+            ErrorKind::PremiumRequired => String::from("Controlling playback requires Spotify Premium"),
+            _ => format!("{:#}", e)
+        };
+
+        Self { kind, message, event, time: Instant::now() }
+    }
+
+    pub fn info(message: String) -> Self {
+        Self { kind: ErrorKind::Info, message, event: None, time: Instant::now() }
+    }
+}
+
+impl ErrorKind {
+    pub fn classify(e: &anyhow::Error, event: Option<&IoEvent>) -> Self {
+        let auth = e.chain().any(|cause| cause.is::<TokenRevoked>() || cause.is::<VaultError>());
+
+        if auth || auth::is_revoked(e) {
+            return Self::Auth;
+        }
+
+        // A 403 can also be a restriction, only the reason confirms Premium is missing
+        match auth::error_reason(e) {
+            Some("PREMIUM_REQUIRED") => return Self::PremiumRequired,
+            Some("NO_ACTIVE_DEVICE") => return Self::NoActiveDevice,
+            _ => ()
+        }
+
+        // Spotify answers player commands with this when no device is playing
+        let player_command = matches!(
+            event,
+            Some(IoEvent::PushPlayback(_) | IoEvent::TransferPlayback(_) | IoEvent::AddToQueue(_))
+        );
+
+        match auth::status_code(e) {
+            Some(429) => Self::RateLimit,
+            Some(404) if player_command => Self::NoActiveDevice,
+            Some(status) if status >= 500 => Self::Network,
+            Some(_) => Self::Other,
+            None if is_network_error(e) => Self::Network,
+            None => Self::Other
+        }
+    }
+}
+
+fn is_network_error(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| matches!(
+        cause.downcast_ref::<ClientError>(),
+        Some(ClientError::Http(http)) if !matches!(http.as_ref(), HttpError::StatusCode(_))
+    ))
+}
@@ -0,0 +1,96 @@
+use crate::AppResult;
+
+use std::collections::HashMap;
+use anyhow::Context;
+use rand::{Rng, distributions::Alphanumeric};
+use sha2::{Digest, Sha256};
+use url::Url;
+use rspotify::{
+    prelude::*,
+    AuthCodeSpotify
+};
+
+const AUTHORIZE_URL: &str = "https://accounts.spotify.com/authorize";
+const VERIFIER_LENGTH: usize = 128;
+
+pub struct Verifier {
+    pub verifier: String,
+    pub challenge: String
+}
+
+impl Verifier {
+    pub fn generate() -> Self {
+        let verifier = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(VERIFIER_LENGTH)
+            .map(char::from)
+            .collect::<String>();
+
+        let challenge = base64::encode_config(
+            Sha256::digest(verifier.as_bytes()),
+            base64::URL_SAFE_NO_PAD
+        );
+
+        Self { verifier, challenge }
+    }
+}
+
+pub fn authorize_url(spotify: &AuthCodeSpotify, verifier: &Verifier) -> AppResult<Url> {
+    let scopes = spotify.oauth.scopes
+        .iter()
+        .map(|s| s.as_str())
+        .collect::<Vec<&str>>()
+        .join(" ");
+
+    let url = Url::parse_with_params(AUTHORIZE_URL, &[
+        ("client_id", spotify.creds.id.as_str()),
+        ("response_type", "code"),
+        ("redirect_uri", spotify.oauth.redirect_uri.as_str()),
+        ("code_challenge_method", "S256"),
+        ("code_challenge", verifier.challenge.as_str()),
+        ("state", spotify.oauth.state.as_str()),
+        ("scope", scopes.as_str())
+    ])?;
+
+    Ok(url)
+}
+
+pub async fn request_token(spotify: &AuthCodeSpotify, code: &str, verifier: &Verifier) -> AppResult<()> {
+    let mut data = HashMap::new();
+    data.insert("grant_type", "authorization_code");
+    data.insert("code", code);
+    data.insert("redirect_uri", spotify.oauth.redirect_uri.as_str());
+    data.insert("client_id", spotify.creds.id.as_str());
+    data.insert("code_verifier", verifier.verifier.as_str());
+
+    let token = spotify.fetch_access_token(&data, None).await
+        .context("Unable to exchange the auth code for a token")?;
+
+    *spotify.token.lock().await.unwrap() = Some(token);
+
+    Ok(())
+}
+
+// Tokens acquired with PKCE can be refreshed with the client id alone
+pub async fn refresh_token(spotify: &AuthCodeSpotify) -> AppResult<()> {
+    let refresh_token = spotify.token.lock().await.unwrap()
+        .as_ref()
+        .and_then(|t| t.refresh_token.clone())
+        .context("The cached token has no refresh token")?;
+
+    let mut data = HashMap::new();
+    data.insert("grant_type", "refresh_token");
+    data.insert("refresh_token", refresh_token.as_str());
+    data.insert("client_id", spotify.creds.id.as_str());
+
+    let mut token = spotify.fetch_access_token(&data, None).await
+        .context("Unable to refresh the token")?;
+
+    if token.refresh_token.is_none() {
+        token.refresh_token = Some(refresh_token);
+    }
+
+    *spotify.token.lock().await.unwrap() = Some(token);
+
+    Ok(())
+}
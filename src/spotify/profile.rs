@@ -0,0 +1,191 @@
+use crate::{AppResult, app::paths};
+
+use std::{
+    fs,
+    path::{Path, PathBuf}
+};
+use anyhow::{Context, bail};
+use serde::{Serialize, Deserialize};
+
+pub const DEFAULT_PROFILE: &str = "default";
+
+// rspotify's default token cache, relative to the working directory
+const LEGACY_TOKEN_CACHE: &str = ".spotify_token_cache.json";
+
+// Seconds, shorter polling intervals would flood the Web API
+const MIN_POLL_INTERVAL: u64 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    pub name: String
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ProfileSettings {
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub redirect_uri: Option<String>,
+    #[serde(default)]
+    pub encrypt_token: bool,
+    // Seconds before an unlocked encrypted token cache locks again
+    pub unlock_timeout: Option<u64>,
+    #[serde(default)]
+    pub polling: PollingSettings
+}
+
+// Seconds between playback fetches in the UI
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PollingSettings {
+    pub playing: u64,
+    pub paused: u64,
+    // Used instead of the above when they're shorter and the window isn't focused
+    pub unfocused: u64,
+    // Doubles on every poll without an active device, up to the maximum
+    pub no_device: u64,
+    pub no_device_max: u64
+}
+
+impl Default for PollingSettings {
+    fn default() -> Self {
+        Self {
+            playing: 5,
+            paused: 15,
+            unfocused: 30,
+            no_device: 10,
+            no_device_max: 120
+        }
+    }
+}
+
+impl PollingSettings {
+    fn clamped(self) -> Self {
+        Self {
+            playing: self.playing.max(MIN_POLL_INTERVAL),
+            paused: self.paused.max(MIN_POLL_INTERVAL),
+            unfocused: self.unfocused.max(MIN_POLL_INTERVAL),
+            no_device: self.no_device.max(MIN_POLL_INTERVAL),
+            no_device_max: self.no_device_max.max(MIN_POLL_INTERVAL)
+        }
+    }
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self { name: String::from(DEFAULT_PROFILE) }
+    }
+}
+
+impl Profile {
+    pub fn new(name: &str) -> AppResult<Self> {
+        let valid = !name.is_empty() && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+        if !valid {
+            bail!("Invalid profile name '{}', use only letters, digits, '-' and '_'", name);
+        }
+
+        Ok(Self { name: name.to_owned() })
+    }
+
+    pub fn list() -> AppResult<Vec<Profile>> {
+        let mut profiles = vec![Profile::default()];
+
+        for entry in fs::read_dir(profiles_dir()?)? {
+            let entry = entry?;
+
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+
+            if let Some(profile) = entry.file_name().to_str().and_then(|n| Profile::new(n).ok()) {
+                if !profiles.contains(&profile) {
+                    profiles.push(profile);
+                }
+            }
+        }
+
+        profiles[1..].sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(profiles)
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.name == DEFAULT_PROFILE
+    }
+
+    pub fn dir(&self) -> AppResult<PathBuf> {
+        let dir = profiles_dir()?.join(&self.name);
+        paths::create_private_dir(&dir)?;
+
+        Ok(dir)
+    }
+
+    pub fn token_cache_path(&self) -> AppResult<PathBuf> {
+        Ok(self.dir()?.join("token.json"))
+    }
+
+    // Moves a token cache left in the working directory by older versions
+    pub fn migrate_legacy_token_cache(&self) -> AppResult<()> {
+        if !self.is_default() {
+            return Ok(());
+        }
+
+        let path = self.token_cache_path()?;
+        let legacy = Path::new(LEGACY_TOKEN_CACHE);
+
+        if !legacy.is_file() || path.exists() {
+            return Ok(());
+        }
+
+        paths::create_private_file(&path)?;
+        fs::copy(legacy, &path)
+            .with_context(|| format!("Unable to migrate token cache to {}", path.display()))?;
+        fs::remove_file(legacy)?;
+
+        eprintln!("Moved token cache from {} to {}", legacy.display(), path.display());
+
+        Ok(())
+    }
+
+    // Unencrypted caches that may hold a token, newest first
+    pub fn plaintext_token_paths(&self) -> AppResult<Vec<PathBuf>> {
+        let mut paths = vec![self.token_cache_path()?];
+
+        if self.is_default() {
+            paths.push(PathBuf::from(LEGACY_TOKEN_CACHE));
+        }
+
+        Ok(paths)
+    }
+
+    pub fn encrypted_token_path(&self) -> AppResult<PathBuf> {
+        Ok(self.dir()?.join("token.enc"))
+    }
+
+    pub fn settings(&self) -> AppResult<ProfileSettings> {
+        let path = self.dir()?.join("settings.json");
+
+        if !path.exists() {
+            return Ok(ProfileSettings::default());
+        }
+
+        let contents = fs::read_to_string(&path)?;
+        let mut settings: ProfileSettings = serde_json::from_str(&contents)
+            .with_context(|| format!("Invalid profile settings in {}", path.display()))?;
+
+        settings.polling = settings.polling.clamped();
+
+        Ok(settings)
+    }
+}
+
+fn profiles_dir() -> AppResult<PathBuf> {
+    let dir = paths::data_dir()?.join("profiles");
+
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Unable to create directory {}", dir.display()))?;
+
+    Ok(dir)
+}
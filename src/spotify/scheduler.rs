@@ -0,0 +1,234 @@
+use crate::AppResult;
+
+use std::{
+    fmt,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration
+};
+use serde::Deserialize;
+use rspotify::{ClientError, ClientResult, http::HttpError};
+use tokio::{
+    sync::watch,
+    time::{Instant, sleep, sleep_until}
+};
+
+const MAX_RETRIES: u32 = 5;
+const BASE_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+// Used when a 429 comes without a usable Retry-After header
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
+// A bogus Retry-After would hold up every request indefinitely otherwise
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(120);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    // Started by the user, waits for nothing but rate limits
+    Interactive,
+    // Polling and prefetching, yields to interactive requests
+    Background
+}
+
+// Every Web API call of the IO thread goes through here. Rate limits pause
+// all requests until Retry-After has passed, transient failures are retried
+// with exponential backoff.
+#[derive(Clone)]
+pub struct Scheduler {
+    inner: Arc<Inner>
+}
+
+struct Inner {
+    retry_at: Mutex<Option<Instant>>,
+    // Interactive events that are queued or running
+    interactive: watch::Sender<usize>
+}
+
+// Keeps background requests waiting until it's dropped
+pub struct Reservation {
+    inner: Arc<Inner>
+}
+
+enum Failure {
+    RateLimited(Duration),
+    Transient,
+    Permanent
+}
+
+// Error response of the Web API, with the body read so the reason is known
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: u16,
+    pub message: String,
+    // Only sent for player commands, e.g. PREMIUM_REQUIRED
+    pub reason: Option<String>
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: ErrorObject
+}
+
+#[derive(Deserialize)]
+struct ErrorObject {
+    message: String,
+    reason: Option<String>
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (status {})", self.message, self.status)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        let (interactive, _) = watch::channel(0);
+
+        Self {
+            inner: Arc::new(Inner {
+                retry_at: Mutex::new(None),
+                interactive
+            })
+        }
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.inner.interactive.send_modify(|count| *count -= 1);
+    }
+}
+
+impl Scheduler {
+    // Taken as soon as an interactive event is received, so background
+    // requests sent after it don't overtake it
+    pub fn reserve(&self) -> Reservation {
+        self.inner.interactive.send_modify(|count| *count += 1);
+        Reservation { inner: self.inner.clone() }
+    }
+
+    pub async fn call<T, F, Fut>(&self, priority: Priority, mut request: F) -> AppResult<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = ClientResult<T>>
+    {
+        let mut attempt = 0;
+
+        loop {
+            self.wait_turn(priority).await;
+
+            let e = match request().await {
+                Ok(result) => return Ok(result),
+                Err(e) => e
+            };
+
+            attempt += 1;
+
+            if attempt > MAX_RETRIES {
+                return Err(read_error(e).await);
+            }
+
+            match classify(&e) {
+                Failure::RateLimited(retry_after) => self.pause(retry_after),
+                Failure::Transient => sleep(backoff(attempt)).await,
+                Failure::Permanent => return Err(read_error(e).await)
+            }
+        }
+    }
+
+    async fn wait_turn(&self, priority: Priority) {
+        let mut interactive = self.inner.interactive.subscribe();
+
+        loop {
+            let retry_at = *self.inner.retry_at.lock().unwrap();
+
+            if let Some(retry_at) = retry_at.filter(|at| *at > Instant::now()) {
+                sleep_until(retry_at).await;
+                continue;
+            }
+
+            if priority == Priority::Background && *interactive.borrow_and_update() > 0 {
+                // The sender lives as long as the scheduler
+                let _ = interactive.changed().await;
+                continue;
+            }
+
+            return;
+        }
+    }
+
+    // Holds back every request, not just the one that got rate limited
+    fn pause(&self, retry_after: Duration) {
+        let until = Instant::now() + retry_after;
+        let retry_at = &mut *self.inner.retry_at.lock().unwrap();
+
+        if retry_at.map_or(true, |at| at < until) {
+            *retry_at = Some(until);
+        }
+    }
+}
+
+fn classify(e: &ClientError) -> Failure {
+    let http = match e {
+        ClientError::Http(http) => http.as_ref(),
+        _ => return Failure::Permanent
+    };
+
+    let response = match http {
+        HttpError::StatusCode(response) => response,
+        // Connection failures and timeouts
+        _ => return Failure::Transient
+    };
+
+    match response.status().as_u16() {
+        429 => {
+            let retry_after = response.headers()
+                .get("retry-after")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse::<u64>().ok())
+                .map_or(DEFAULT_RETRY_AFTER, Duration::from_secs)
+                .min(MAX_RETRY_AFTER);
+
+            Failure::RateLimited(retry_after)
+        },
+        500 | 502 | 503 | 504 => Failure::Transient,
+        _ => Failure::Permanent
+    }
+}
+
+// Error responses carry the message and reason in their body
+async fn read_error(e: ClientError) -> anyhow::Error {
+    let response = match e {
+        ClientError::Http(http) => match *http {
+            HttpError::StatusCode(response) => response,
+            http => return ClientError::Http(Box::new(http)).into()
+        },
+        e => return e.into()
+    };
+
+    let status = response.status();
+
+    let error = match response.text().await {
+        Ok(body) => serde_json::from_str::<ErrorResponse>(&body).ok(),
+        Err(_) => None
+    };
+
+    let (message, reason) = match error {
+        Some(ErrorResponse { error }) => (error.message, error.reason),
+        None => (status.canonical_reason().unwrap_or("Request failed").to_owned(), None)
+    };
+
+    ApiError { status: status.as_u16(), message, reason }.into()
+}
+
+// Full jitter, so the retries of concurrent requests spread out
+fn backoff(attempt: u32) -> Duration {
+    let ceiling = BASE_BACKOFF
+        .saturating_mul(2u32.saturating_pow(attempt - 1))
+        .min(MAX_BACKOFF);
+
+    ceiling.mul_f64(rand::random::<f64>())
+}
@@ -0,0 +1,84 @@
+use crate::AppResult;
+
+use std::{collections::HashSet, fmt};
+use anyhow::anyhow;
+
+pub const READ_PLAYBACK: &str = "user-read-playback-state";
+pub const READ_CURRENTLY_PLAYING: &str = "user-read-currently-playing";
+pub const MODIFY_PLAYBACK: &str = "user-modify-playback-state";
+pub const READ_PLAYLISTS: &str = "playlist-read-private";
+pub const READ_COLLABORATIVE_PLAYLISTS: &str = "playlist-read-collaborative";
+pub const MODIFY_PRIVATE_PLAYLISTS: &str = "playlist-modify-private";
+pub const MODIFY_PUBLIC_PLAYLISTS: &str = "playlist-modify-public";
+pub const READ_LIBRARY: &str = "user-library-read";
+pub const MODIFY_LIBRARY: &str = "user-library-modify";
+
+// Requested on the first login, everything else is asked for when a feature needs it
+pub const MINIMAL: &[&str] = &[
+    READ_PLAYBACK,
+    READ_CURRENTLY_PLAYING,
+    READ_PLAYLISTS,
+    READ_COLLABORATIVE_PLAYLISTS
+];
+
+pub const OPTIONAL: &[&str] = &[
+    MODIFY_PLAYBACK,
+    READ_LIBRARY,
+    MODIFY_LIBRARY,
+    MODIFY_PRIVATE_PLAYLISTS,
+    MODIFY_PUBLIC_PLAYLISTS
+];
+
+#[derive(Debug)]
+pub struct MissingScopes(pub Vec<&'static str>);
+
+impl fmt::Display for MissingScopes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let needed = self.0
+            .iter()
+            .map(|scope| format!("{} ({})", describe(scope), scope))
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        write!(f, "This needs permission to {}, which hasn't been granted yet", needed)
+    }
+}
+
+impl std::error::Error for MissingScopes {}
+
+pub fn describe(scope: &str) -> &'static str {
+    match scope {
+        READ_PLAYBACK => "read your playback state",
+        READ_CURRENTLY_PLAYING => "read what you're playing",
+        MODIFY_PLAYBACK => "control your playback",
+        READ_PLAYLISTS => "read your private playlists",
+        READ_COLLABORATIVE_PLAYLISTS => "read your collaborative playlists",
+        MODIFY_PRIVATE_PLAYLISTS => "edit your private playlists",
+        MODIFY_PUBLIC_PLAYLISTS => "edit your public playlists",
+        READ_LIBRARY => "read your Liked Songs",
+        MODIFY_LIBRARY => "change your Liked Songs",
+        _ => "access your account"
+    }
+}
+
+pub fn missing(granted: &HashSet<String>, required: &[&'static str]) -> Vec<&'static str> {
+    required
+        .iter()
+        .filter(|scope| !granted.contains(**scope))
+        .copied()
+        .collect()
+}
+
+pub fn check(granted: &HashSet<String>, required: &[&'static str]) -> AppResult<()> {
+    match missing(granted, required) {
+        missing if missing.is_empty() => Ok(()),
+        missing => Err(anyhow!(MissingScopes(missing)))
+    }
+}
+
+// Scopes the failed request would have needed, if that's why it failed
+pub fn find_missing(e: &anyhow::Error) -> Option<Vec<&'static str>> {
+    e.chain()
+        .find_map(|cause| cause.downcast_ref::<MissingScopes>())
+        .map(|missing| missing.0.clone())
+}
@@ -0,0 +1,95 @@
+use crate::AppResult;
+use super::uri::SpotifyUri;
+
+use anyhow::Context;
+use rspotify::{
+    prelude::BaseClient,
+    model::{SearchType, SearchResult},
+    AuthCodeSpotify
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchKind {
+    Track,
+    Album,
+    Artist,
+    Playlist
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchMatch {
+    pub name: String,
+    pub detail: String,
+    pub uri: SpotifyUri
+}
+
+impl SearchKind {
+    fn search_type(self) -> SearchType {
+        match self {
+            SearchKind::Track => SearchType::Track,
+            SearchKind::Album => SearchType::Album,
+            SearchKind::Artist => SearchType::Artist,
+            SearchKind::Playlist => SearchType::Playlist
+        }
+    }
+}
+
+// Results are returned in the order of relevance reported by the Web API
+pub async fn search(
+    client: &AuthCodeSpotify,
+    kind: SearchKind,
+    query: &str,
+    limit: u32
+) -> AppResult<Vec<SearchMatch>> {
+    let result = client
+        .search(query, &kind.search_type(), None, None, Some(limit), None).await
+        .context("Unable to search the Spotify catalog")?;
+
+    let matches = match result {
+        SearchResult::Tracks(page) => page.items
+            .into_iter()
+            .filter_map(|t| Some(SearchMatch {
+                detail: join_artists(t.artists.iter().map(|a| a.name.as_str())),
+                uri: SpotifyUri::Track(t.id?),
+                name: t.name
+            }))
+            .collect(),
+
+        SearchResult::Albums(page) => page.items
+            .into_iter()
+            .filter_map(|a| Some(SearchMatch {
+                detail: join_artists(a.artists.iter().map(|a| a.name.as_str())),
+                uri: SpotifyUri::Album(a.id?),
+                name: a.name
+            }))
+            .collect(),
+
+        SearchResult::Artists(page) => page.items
+            .into_iter()
+            .map(|a| SearchMatch {
+                detail: a.genres.join(", "),
+                uri: SpotifyUri::Artist(a.id),
+                name: a.name
+            })
+            .collect(),
+
+        SearchResult::Playlists(page) => page.items
+            .into_iter()
+            .map(|p| SearchMatch {
+                detail: p.owner.display_name.unwrap_or_default(),
+                uri: SpotifyUri::Playlist(p.id),
+                name: p.name
+            })
+            .collect(),
+
+        _ => Vec::new()
+    };
+
+    Ok(matches)
+}
+
+fn join_artists<'a>(artists: impl Iterator<Item = &'a str>) -> String {
+    artists
+        .collect::<Vec<&str>>()
+        .join(", ")
+}
@@ -0,0 +1,93 @@
+use rspotify::{
+    prelude::{Id, PlayableId, PlayContextId},
+    model::{
+        AlbumId,
+        ArtistId,
+        EpisodeId,
+        PlaylistId,
+        ShowId,
+        TrackId
+    }
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpotifyUri {
+    Track(TrackId),
+    Episode(EpisodeId),
+    Album(AlbumId),
+    Artist(ArtistId),
+    Playlist(PlaylistId),
+    Show(ShowId)
+}
+
+impl SpotifyUri {
+    // Accepts both `spotify:track:<id>` URIs and `https://open.spotify.com/track/<id>` links
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim();
+
+        let (kind, id) = if let Some(rest) = input.strip_prefix("spotify:") {
+            match rest.split(':').collect::<Vec<&str>>()[..] {
+                [kind, id] => (kind, id),
+                ["user", _, kind, id] => (kind, id),
+                _ => return None
+            }
+        } else {
+            let rest = input
+                .strip_prefix("https://")
+                .or_else(|| input.strip_prefix("http://"))
+                .unwrap_or(input);
+
+            let path = rest
+                .strip_prefix("open.spotify.com/")?
+                .split(|c| c == '?' || c == '#')
+                .next()?;
+
+            let mut segments = path
+                .split('/')
+                .filter(|s| !s.is_empty() && !s.starts_with("intl-"));
+
+            (segments.next()?, segments.next()?)
+        };
+
+        let uri = match kind {
+            "track" => SpotifyUri::Track(TrackId::from_id(id).ok()?),
+            "episode" => SpotifyUri::Episode(EpisodeId::from_id(id).ok()?),
+            "album" => SpotifyUri::Album(AlbumId::from_id(id).ok()?),
+            "artist" => SpotifyUri::Artist(ArtistId::from_id(id).ok()?),
+            "playlist" => SpotifyUri::Playlist(PlaylistId::from_id(id).ok()?),
+            "show" => SpotifyUri::Show(ShowId::from_id(id).ok()?),
+            _ => return None
+        };
+
+        Some(uri)
+    }
+
+    pub fn uri(&self) -> String {
+        match self {
+            SpotifyUri::Track(id) => id.uri(),
+            SpotifyUri::Episode(id) => id.uri(),
+            SpotifyUri::Album(id) => id.uri(),
+            SpotifyUri::Artist(id) => id.uri(),
+            SpotifyUri::Playlist(id) => id.uri(),
+            SpotifyUri::Show(id) => id.uri()
+        }
+    }
+
+    pub fn as_playable(&self) -> Option<&dyn PlayableId> {
+        match self {
+            SpotifyUri::Track(id) => Some(id),
+            SpotifyUri::Episode(id) => Some(id),
+            _ => None
+        }
+    }
+
+    pub fn as_context(&self) -> Option<&dyn PlayContextId> {
+        match self {
+            SpotifyUri::Album(id) => Some(id),
+            SpotifyUri::Artist(id) => Some(id),
+            SpotifyUri::Playlist(id) => Some(id),
+            SpotifyUri::Show(id) => Some(id),
+            _ => None
+        }
+    }
+}
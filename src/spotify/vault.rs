@@ -0,0 +1,312 @@
+use crate::{AppResult, app::paths};
+use super::profile::Profile;
+
+use std::{
+    fmt,
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant}
+};
+use anyhow::{Context, anyhow};
+use argon2::Argon2;
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Key, Nonce
+};
+use serde::{Serialize, Deserialize};
+use tokio::sync::{Mutex as AsyncMutex, MutexGuard};
+use rspotify::Token;
+
+pub const DEFAULT_UNLOCK_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+const FORMAT_VERSION: u32 = 1;
+const SALT_LENGTH: usize = 16;
+
+// Token cache encrypted with a key derived from a passphrase. The key is
+// only kept in memory until the unlock timeout expires.
+#[derive(Clone, Default)]
+pub struct Vault {
+    inner: Arc<Mutex<Inner>>,
+    // Held while refreshing the token, shared by all users of the profile
+    refreshing: Arc<AsyncMutex<()>>
+}
+
+#[derive(Default)]
+struct Inner {
+    path: Option<PathBuf>,
+    // Imported on the first unlock, then deleted
+    plaintext: Vec<PathBuf>,
+    timeout: Duration,
+    key: Option<UnlockedKey>
+}
+
+struct UnlockedKey {
+    key: [u8; 32],
+    salt: [u8; SALT_LENGTH],
+    expires: Instant
+}
+
+#[derive(Serialize, Deserialize)]
+struct EncryptedToken {
+    version: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String
+}
+
+// The plaintext caches deleted while unlocking are up to the caller to report
+pub struct Unlocked {
+    pub token: Option<Token>,
+    pub deleted: Vec<PathBuf>
+}
+
+impl Unlocked {
+    pub fn notices(&self) -> impl Iterator<Item = String> + '_ {
+        self.deleted
+            .iter()
+            .map(|path| format!("Deleted the unencrypted token cache at {}", path.display()))
+    }
+}
+
+// Keeps the passphrase out of debug output
+pub struct Passphrase(pub String);
+
+impl fmt::Debug for Passphrase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Passphrase(..)")
+    }
+}
+
+#[derive(Debug)]
+pub enum VaultError {
+    Locked,
+    Undecryptable(PathBuf)
+}
+
+impl fmt::Display for VaultError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Locked => write!(f, "The token cache is locked"),
+            Self::Undecryptable(path) => write!(
+                f,
+                "Unable to decrypt the token cache at {}, either the passphrase is wrong \
+                or the file has been tampered with",
+                path.display()
+            )
+        }
+    }
+}
+
+impl std::error::Error for VaultError {}
+
+impl Vault {
+    // Vault of the given profile, disabled unless its settings ask for encryption
+    pub fn for_profile(profile: &Profile) -> AppResult<Self> {
+        let settings = profile.settings()?;
+
+        if !settings.encrypt_token {
+            return Ok(Self::default());
+        }
+
+        let inner = Inner {
+            path: Some(profile.encrypted_token_path()?),
+            plaintext: profile.plaintext_token_paths()?,
+            timeout: settings.unlock_timeout
+                .map_or(DEFAULT_UNLOCK_TIMEOUT, Duration::from_secs),
+            key: None
+        };
+
+        Ok(Self {
+            inner: Arc::new(Mutex::new(inner)),
+            refreshing: Default::default()
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.inner.lock().unwrap().path.is_some()
+    }
+
+    pub fn exists(&self) -> bool {
+        self.inner.lock().unwrap().path
+            .as_ref()
+            .map_or(false, |p| p.exists())
+    }
+
+    // Forgets the key once the unlock timeout has expired
+    pub fn is_unlocked(&self) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+
+        if inner.key.as_ref().map_or(false, |k| k.expires <= Instant::now()) {
+            inner.key = None;
+        }
+
+        inner.key.is_some()
+    }
+
+    pub fn needs_unlock(&self) -> bool {
+        self.is_enabled() && !self.is_unlocked()
+    }
+
+    pub async fn refresh_guard(&self) -> MutexGuard<'_, ()> {
+        self.refreshing.lock().await
+    }
+
+    // Derives the key from the passphrase and returns the stored token, if any
+    pub async fn unlock(&self, passphrase: &str) -> AppResult<Unlocked> {
+        let path = self.inner.lock().unwrap().path
+            .clone()
+            .context("The token cache isn't encrypted")?;
+
+        if !path.exists() {
+            let salt = rand::random::<[u8; SALT_LENGTH]>();
+            let key = derive_key(passphrase, salt).await?;
+
+            self.set_key(key, salt);
+            return self.import_plaintext();
+        }
+
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("Unable to read {}", path.display()))?;
+
+        let undecryptable = || VaultError::Undecryptable(path.clone());
+
+        let file = serde_json::from_str::<EncryptedToken>(&contents)
+            .map_err(|_| undecryptable())?;
+
+        if file.version != FORMAT_VERSION {
+            return Err(anyhow!(undecryptable()));
+        }
+
+        let decode = |data: &str| base64::decode(data).map_err(|_| undecryptable());
+
+        let salt: [u8; SALT_LENGTH] = decode(&file.salt)?
+            .try_into()
+            .map_err(|_| undecryptable())?;
+
+        let nonce = decode(&file.nonce)?;
+        let ciphertext = decode(&file.ciphertext)?;
+
+        if nonce.len() != 12 {
+            return Err(anyhow!(undecryptable()));
+        }
+
+        let key = derive_key(passphrase, salt).await?;
+        let plaintext = ChaCha20Poly1305::new(Key::from_slice(&key))
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+            .map_err(|_| undecryptable())?;
+
+        let token = serde_json::from_slice::<Token>(&plaintext)
+            .map_err(|_| undecryptable())?;
+
+        self.set_key(key, salt);
+
+        // Left behind when the plain cache was written after encrypting
+        let deleted = self.remove_plaintext()?;
+
+        Ok(Unlocked { token: Some(token), deleted })
+    }
+
+    fn set_key(&self, key: [u8; 32], salt: [u8; SALT_LENGTH]) {
+        let inner = &mut *self.inner.lock().unwrap();
+        let expires = Instant::now() + inner.timeout;
+
+        inner.key = Some(UnlockedKey { key, salt, expires });
+    }
+
+    // Moves the token of a plaintext cache into the vault
+    fn import_plaintext(&self) -> AppResult<Unlocked> {
+        let plaintext = self.inner.lock().unwrap().plaintext.clone();
+
+        let token = plaintext
+            .iter()
+            .filter_map(|path| fs::read_to_string(path).ok())
+            .find_map(|contents| serde_json::from_str::<Token>(&contents).ok());
+
+        if let Some(token) = &token {
+            self.save(token)?;
+        }
+
+        let deleted = self.remove_plaintext()?;
+
+        Ok(Unlocked { token, deleted })
+    }
+
+    fn remove_plaintext(&self) -> AppResult<Vec<PathBuf>> {
+        let plaintext = self.inner.lock().unwrap().plaintext.clone();
+        let mut deleted = Vec::new();
+
+        for path in plaintext.into_iter().filter(|path| path.is_file()) {
+            paths::remove_file_securely(&path)?;
+            deleted.push(path);
+        }
+
+        Ok(deleted)
+    }
+
+    pub fn lock(&self) {
+        self.inner.lock().unwrap().key = None;
+    }
+
+    pub fn save(&self, token: &Token) -> AppResult<()> {
+        if !self.is_unlocked() {
+            return Err(anyhow!(VaultError::Locked));
+        }
+
+        let inner = self.inner.lock().unwrap();
+        let (path, unlocked) = match (&inner.path, &inner.key) {
+            (Some(path), Some(key)) => (path, key),
+            _ => return Err(anyhow!(VaultError::Locked))
+        };
+
+        let nonce = rand::random::<[u8; 12]>();
+        let plaintext = serde_json::to_vec(token)?;
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&unlocked.key))
+            .encrypt(Nonce::from_slice(&nonce), plaintext.as_ref())
+            .map_err(|_| anyhow!("Unable to encrypt the token"))?;
+
+        let file = EncryptedToken {
+            version: FORMAT_VERSION,
+            salt: base64::encode(unlocked.salt),
+            nonce: base64::encode(nonce),
+            ciphertext: base64::encode(ciphertext)
+        };
+
+        paths::create_private_file(path)?;
+        fs::write(path, serde_json::to_string(&file)?)
+            .with_context(|| format!("Unable to write {}", path.display()))?;
+
+        Ok(())
+    }
+
+    // Deletes the encrypted token and forgets the key
+    pub fn delete(&self) -> AppResult<bool> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.key = None;
+
+        match &inner.path {
+            Some(path) if path.exists() => {
+                fs::remove_file(path)
+                    .with_context(|| format!("Unable to delete {}", path.display()))?;
+                Ok(true)
+            },
+            _ => Ok(false)
+        }
+    }
+}
+
+// Argon2 is slow on purpose, so it runs off the async workers and without
+// holding the lock the UI checks every frame
+async fn derive_key(passphrase: &str, salt: [u8; SALT_LENGTH]) -> AppResult<[u8; 32]> {
+    let passphrase = passphrase.to_owned();
+
+    tokio::task::spawn_blocking(move || -> AppResult<[u8; 32]> {
+        let mut key = [0u8; 32];
+
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+            .map_err(|e| anyhow!("Unable to derive the key: {}", e))?;
+
+        Ok(key)
+    }).await?
+}
@@ -3,6 +3,7 @@
 use glium::glutin;
 use glium::glutin::event::{Event, WindowEvent};
 use glium::glutin::event_loop::{ControlFlow, EventLoop};
+use glium::glutin::platform::run_return::EventLoopExtRunReturn;
 use glium::glutin::window::WindowBuilder;
 use glium::{Display, Surface};
 use imgui::{Context, FontConfig, FontSource, Ui, ConfigFlags};
@@ -20,6 +21,7 @@ pub struct System {
     pub platform: Rc<RefCell<WinitPlatform>>,
     pub renderer: Rc<RefCell<Renderer>>,
     pub font_size: f32,
+    pub focused: bool,
 }
 
 pub fn init(title: &str) -> System {
@@ -78,12 +80,14 @@ pub fn init(title: &str) -> System {
         platform: Rc::new(RefCell::new(platform)),
         renderer: Rc::new(RefCell::new(renderer)),
         font_size,
+        focused: true,
     }
 }
 
 impl System {
+    // Returns once the window has been closed
     pub fn main_loop<F: FnMut(&Self, &mut bool, &mut Ui) + 'static>(mut self, mut run_ui: F) {
-        let event_loop = self.event_loop.take().unwrap();
+        let mut event_loop = self.event_loop.take().unwrap();
         let display = self.display.clone();
         let imgui = self.imgui.clone();
         let platform = self.platform.clone();
@@ -91,7 +95,7 @@ impl System {
 
         let mut last_frame = Instant::now();
 
-        event_loop.run(move |event, _, control_flow| match event {
+        event_loop.run_return(move |event, _, control_flow| match event {
             Event::NewEvents(_) => {
                 let now = Instant::now();
                 imgui.borrow_mut().io_mut().update_delta_time(now - last_frame);
@@ -142,6 +146,10 @@ impl System {
                 ..
             } => *control_flow = ControlFlow::Exit,
             event => {
+                if let Event::WindowEvent { event: WindowEvent::Focused(focused), .. } = event {
+                    self.focused = focused;
+                }
+
                 let display = display.borrow();
                 let gl_window = display.gl_window();
                 platform.borrow_mut().handle_event(imgui.borrow_mut().io_mut(), gl_window.window(), &event);